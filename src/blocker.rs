@@ -0,0 +1,180 @@
+use crate::files;
+
+use log::{info, warn};
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const BEGIN: &str = "# BEGIN tomatina";
+const END: &str = "# END tomatina";
+
+/// The `[block]` section of the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlockConfig {
+    pub domains: Vec<String>,
+    pub hosts_file: PathBuf,
+    /// Log the changes instead of making them.
+    pub dry_run: bool,
+}
+
+impl Default for BlockConfig {
+    fn default() -> Self {
+        Self {
+            domains: Vec::new(),
+            hosts_file: PathBuf::from("/etc/hosts"),
+            dry_run: false,
+        }
+    }
+}
+
+/// Blackholes distracting domains by adding them to a managed block at the end
+/// of the hosts file.
+pub struct HostsBlocker {
+    config: BlockConfig,
+}
+
+impl HostsBlocker {
+    pub fn new(config: BlockConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn block(&self) -> io::Result<()> {
+        self.update(|contents| add_block(contents, &self.config.domains))
+    }
+
+    /// Removes the managed block, including one left behind by a crash.
+    pub fn unblock(&self) -> io::Result<()> {
+        self.update(remove_block)
+    }
+
+    fn update<F: Fn(&str) -> Option<String>>(&self, change: F) -> io::Result<()> {
+        let path = &self.config.hosts_file;
+        let contents = fs::read_to_string(path)?;
+        let updated = match change(&contents) {
+            Some(updated) => updated,
+            None => {
+                warn!(
+                    "{} has a \"{}\" line but no \"{}\", so it's been left alone",
+                    path.display(),
+                    BEGIN,
+                    END
+                );
+                return Ok(());
+            }
+        };
+        if updated == contents {
+            return Ok(());
+        }
+        if self.config.dry_run {
+            info!("Would write to {}:\n{}", path.display(), updated);
+            return Ok(());
+        }
+        files::replace(path, &updated)
+    }
+}
+
+fn add_block(contents: &str, domains: &[String]) -> Option<String> {
+    let mut updated = remove_block(contents)?;
+    if !updated.is_empty() && !updated.ends_with('\n') {
+        updated.push('\n');
+    }
+    updated += BEGIN;
+    updated.push('\n');
+    for domain in domains {
+        updated += &format!("0.0.0.0 {}\n:: {}\n", domain, domain);
+    }
+    updated += END;
+    updated.push('\n');
+    Some(updated)
+}
+
+/// The contents without the managed block, or `None` if it has no end and
+/// there's no telling where the lines that aren't ours start again.
+fn remove_block(contents: &str) -> Option<String> {
+    let mut in_block = false;
+    let mut updated = String::new();
+    for line in contents.split_inclusive('\n') {
+        match line.trim_end() {
+            BEGIN => in_block = true,
+            END if in_block => in_block = false,
+            _ if !in_block => updated += line,
+            _ => {}
+        }
+    }
+    if in_block {
+        None
+    } else {
+        Some(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    const HOSTS: &str = "127.0.0.1 localhost\n::1 localhost\n";
+
+    fn hosts_file(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("tomatina-{}-{}", std::process::id(), name));
+        fs::write(&path, HOSTS).unwrap();
+        path
+    }
+
+    fn blocker(path: &Path, dry_run: bool) -> HostsBlocker {
+        HostsBlocker::new(BlockConfig {
+            domains: vec!["news.ycombinator.com".to_string()],
+            hosts_file: path.to_path_buf(),
+            dry_run,
+        })
+    }
+
+    #[test]
+    fn test_block_adds_and_unblock_removes_the_managed_block() {
+        let path = hosts_file("hosts");
+        let blocker = blocker(&path, false);
+        blocker.block().unwrap();
+        blocker.block().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "127.0.0.1 localhost\n::1 localhost\n\
+             # BEGIN tomatina\n\
+             0.0.0.0 news.ycombinator.com\n\
+             :: news.ycombinator.com\n\
+             # END tomatina\n"
+        );
+        blocker.unblock().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), HOSTS);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_dry_run_leaves_the_file_alone() {
+        let path = hosts_file("hosts-dry-run");
+        blocker(&path, true).block().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), HOSTS);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_remove_block_keeps_lines_around_it() {
+        assert_eq!(
+            remove_block("a\n# BEGIN tomatina\n0.0.0.0 x\n# END tomatina\nb"),
+            Some("a\nb".to_string())
+        );
+    }
+
+    #[test]
+    fn test_block_without_an_end_is_left_alone() {
+        let path = hosts_file("hosts-no-end");
+        let contents = format!("{}# BEGIN tomatina\n0.0.0.0 x\n192.168.1.2 nas\n", HOSTS);
+        fs::write(&path, &contents).unwrap();
+        let blocker = blocker(&path, false);
+        blocker.unblock().unwrap();
+        blocker.block().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), contents);
+        fs::remove_file(path).unwrap();
+    }
+}
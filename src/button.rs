@@ -1,13 +1,22 @@
-use libusb::{Context, DeviceHandle};
+use crate::error::{Error, Result};
+
+use rusb::{Context, Device, DeviceHandle, UsbContext};
+use serde::Deserialize;
+use std::ops::Range;
 use std::time::Duration;
 
-const USBBTN_VENDOR_ID: u16 = 0xD209;
-const USBBTN_PRODUCT_ID: u16 = 0x1200;
-const USBBTN_INTERFACE: u16 = 0;
+pub const USBBTN_VENDOR_ID: u16 = 0xD209;
+pub const USBBTN_PRODUCT_ID: u16 = 0x1200;
+pub const USBBTN_INTERFACE: u16 = 0;
 const USBBTN_VALUE: u16 = 0x0200;
 const UM_REQUEST_TYPE: u8 = 0x21;
 const UM_REQUEST: u8 = 9;
 const TIMEOUT: Duration = Duration::from_millis(2000);
+/// What the button lights up as while it's held down.
+pub const PRESSED_COLOR: Color = Color(255, 255, 255);
+/// Where the key sequences go in the button's configuration.
+const PRIMARY_KEYS: Range<usize> = 10..34;
+const SECONDARY_KEYS: Range<usize> = 34..64;
 
 #[allow(dead_code)]
 enum ButtonAction {
@@ -16,6 +25,7 @@ enum ButtonAction {
     Both = 2,
 }
 
+#[derive(Clone, Copy)]
 enum Key {
     T = 0x17,
     U = 0x18,
@@ -24,26 +34,110 @@ enum Key {
     CmdLeft = 0x73,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq, Deserialize)]
 pub struct Color(pub u8, pub u8, pub u8);
 
-pub struct Button<'a> {
-    device: DeviceHandle<'a>,
+/// A configuration saved on the button itself, as it came from the factory or
+/// as another program set it up.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ButtonData {
+    pub released: Color,
+    pub pressed: Color,
+    /// Key codes typed by a short press, and by a long one.
+    #[serde(default)]
+    pub primary: Vec<u8>,
+    #[serde(default)]
+    pub secondary: Vec<u8>,
+}
+
+/// The `[exit]` section of the config file: what to leave the button as when
+/// tomatina exits.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ExitConfig {
+    pub color: Color,
+    /// Saved to the button in place of tomatina's colors and key bindings.
+    pub restore: Option<ButtonData>,
+}
+
+impl Default for ExitConfig {
+    fn default() -> Self {
+        Self {
+            color: Color(0, 0, 0),
+            restore: None,
+        }
+    }
+}
+
+impl ExitConfig {
+    /// Checks what the config file format can't.
+    pub fn check(&self) -> std::result::Result<(), String> {
+        match &self.restore {
+            Some(data) if data.primary.len() > PRIMARY_KEYS.len() => Err(format!(
+                "the button takes at most {} primary keys",
+                PRIMARY_KEYS.len()
+            )),
+            Some(data) if data.secondary.len() > SECONDARY_KEYS.len() => Err(format!(
+                "the button takes at most {} secondary keys",
+                SECONDARY_KEYS.len()
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+pub struct Button {
+    device: DeviceHandle<Context>,
+    exit: ExitConfig,
+    detach_kernel_driver: bool,
+}
+
+/// Finds the button without opening it.
+pub fn find(context: &Context) -> Result<Device<Context>> {
+    // look through the devices ourselves, rather than have libusb do it, so a
+    // button we aren't allowed to open isn't mistaken for a missing one
+    for device in context.devices()?.iter() {
+        let descriptor = device.device_descriptor()?;
+        if (descriptor.vendor_id(), descriptor.product_id())
+            == (USBBTN_VENDOR_ID, USBBTN_PRODUCT_ID)
+        {
+            return Ok(device);
+        }
+    }
+    Err(Error::NoButton)
 }
 
-impl<'a> Button<'a> {
-    pub fn connect(context: &'a Context) -> Self {
-        let device = context
-            .open_device_with_vid_pid(USBBTN_VENDOR_ID, USBBTN_PRODUCT_ID)
-            .expect("failed to find USBButton");
-        Self { device }
+impl Button {
+    /// Opens the button. With `detach_kernel_driver` set, a kernel driver
+    /// bound to it is put aside for each write, and given it back straight
+    /// after so the button carries on typing.
+    pub fn connect(context: &Context, detach_kernel_driver: bool) -> Result<Self> {
+        Ok(Self {
+            device: find(context)?.open()?,
+            exit: ExitConfig::default(),
+            detach_kernel_driver,
+        })
     }
 
-    pub fn configure(&self, color: &Color) {
-        self.set_button_data(color, &Color(255, 255, 255));
+    /// Sets what the button is left as once it's dropped.
+    pub fn set_exit(&mut self, exit: ExitConfig) {
+        self.exit = exit;
     }
 
-    fn set_button_data(&self, released_color: &Color, pressed_color: &Color) {
+    pub fn configure(&self, color: &Color) -> Result<()> {
+        let primary = [Key::CtrlLeft, Key::AltLeft, Key::CmdLeft, Key::T].map(|key| key as u8);
+        let secondary = [Key::CtrlLeft, Key::AltLeft, Key::CmdLeft, Key::U].map(|key| key as u8);
+        self.set_button_data(color, &PRESSED_COLOR, &primary, &secondary)
+    }
+
+    fn set_button_data(
+        &self,
+        released_color: &Color,
+        pressed_color: &Color,
+        primary: &[u8],
+        secondary: &[u8],
+    ) -> Result<()> {
         let mut buf: [u8; 64] = [0; 64];
 
         // header
@@ -60,41 +154,85 @@ impl<'a> Button<'a> {
         buf[9] = pressed_color.2;
 
         // Primary key sequence
-        buf[10] = Key::CtrlLeft as u8;
-        buf[11] = Key::AltLeft as u8;
-        buf[12] = Key::CmdLeft as u8;
-        buf[13] = Key::T as u8;
+        for (slot, key) in buf[PRIMARY_KEYS].iter_mut().zip(primary) {
+            *slot = *key;
+        }
 
         // Secondary key sequence
-        buf[34] = Key::CtrlLeft as u8;
-        buf[35] = Key::AltLeft as u8;
-        buf[36] = Key::CmdLeft as u8;
-        buf[37] = Key::U as u8;
+        for (slot, key) in buf[SECONDARY_KEYS].iter_mut().zip(secondary) {
+            *slot = *key;
+        }
 
-        for offset in (0..buf.len()).step_by(4) {
-            self.device
+        self.write(buf.chunks(4))
+    }
+
+    pub fn set_color(&self, color: &Color) -> Result<()> {
+        self.write([&[1, color.0, color.1, color.2][..]])
+    }
+
+    /// Sends each of `reports`, detaching the kernel driver around them if
+    /// asked to. Only an interface taken from the driver is claimed: the
+    /// button takes control transfers without, and macOS won't let us.
+    fn write<'b>(&self, reports: impl IntoIterator<Item = &'b [u8]>) -> Result<()> {
+        let device = &self.device;
+        let interface = USBBTN_INTERFACE as u8;
+        let detach = self.detach_kernel_driver
+            && match device.kernel_driver_active(interface) {
+                Ok(active) => active,
+                // not every platform can tell, or has drivers to detach
+                Err(rusb::Error::NotSupported) => false,
+                Err(e) => return Err(e.into()),
+            };
+        if detach {
+            device
+                .detach_kernel_driver(interface)
+                .map_err(Error::KernelDriver)?;
+            if let Err(e) = device.claim_interface(interface) {
+                let _ = device.attach_kernel_driver(interface);
+                return Err(e.into());
+            }
+        }
+        let result = reports.into_iter().try_for_each(|report| {
+            device
                 .write_control(
                     UM_REQUEST_TYPE,
                     UM_REQUEST,
                     USBBTN_VALUE,
                     USBBTN_INTERFACE,
-                    &buf[offset..offset + 4],
+                    report,
                     TIMEOUT,
                 )
-                .expect("transfer failed");
+                .map(drop)
+                .map_err(Error::from)
+        });
+        if !detach {
+            return result;
         }
+        let _ = device.release_interface(interface);
+        // without its driver the button can't type its keys
+        let reattached = device
+            .attach_kernel_driver(interface)
+            .map_err(Error::KernelDriver);
+        result.and(reattached)
     }
+}
 
-    pub fn set_color(&self, color: &Color) {
-        self.device
-            .write_control(
-                UM_REQUEST_TYPE,
-                UM_REQUEST,
-                USBBTN_VALUE,
-                USBBTN_INTERFACE,
-                &[1, color.0, color.1, color.2],
-                TIMEOUT,
-            )
-            .expect("transfer failed");
+impl Drop for Button {
+    fn drop(&mut self) {
+        // this may be on the way out of a panic, so don't risk another
+        match &self.exit.restore {
+            // the restored configuration brings its own color with it
+            Some(data) => {
+                let _ = self.set_button_data(
+                    &data.released,
+                    &data.pressed,
+                    &data.primary,
+                    &data.secondary,
+                );
+            }
+            None => {
+                let _ = self.set_color(&self.exit.color);
+            }
+        }
     }
 }
@@ -0,0 +1,71 @@
+use std::time::{Instant, SystemTime};
+
+#[cfg(test)]
+use chrono::{Local, TimeZone};
+#[cfg(test)]
+use std::{cell::Cell, rc::Rc, time::Duration};
+
+pub trait Clock {
+    /// Monotonic time, which doesn't advance while the machine is suspended.
+    fn now(&self) -> Instant;
+
+    fn system_time(&self) -> SystemTime;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time so a test
+/// can keep a handle to the clock it gave away. It starts at noon local time on
+/// Monday 2020-09-14.
+#[cfg(test)]
+#[derive(Clone)]
+pub struct MockClock {
+    now: Rc<Cell<Instant>>,
+    system_time: Rc<Cell<SystemTime>>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub fn new() -> Self {
+        Self {
+            now: Rc::new(Cell::new(Instant::now())),
+            system_time: Rc::new(Cell::new(
+                Local
+                    .with_ymd_and_hms(2020, 9, 14, 12, 0, 0)
+                    .unwrap()
+                    .into(),
+            )),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+        self.system_time.set(self.system_time.get() + duration);
+    }
+
+    /// Moves the wall clock alone, as happens while the machine is asleep.
+    pub fn suspend(&self, duration: Duration) {
+        self.system_time.set(self.system_time.get() + duration);
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn system_time(&self) -> SystemTime {
+        self.system_time.get()
+    }
+}
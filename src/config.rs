@@ -0,0 +1,104 @@
+use crate::blocker::BlockConfig;
+use crate::button::ExitConfig;
+use crate::dnd::DndConfig;
+use crate::error::{Error, Result};
+use crate::hooks::HooksConfig;
+use crate::http::HttpConfig;
+use crate::idle::IdleConfig;
+use crate::mqtt::MqttConfig;
+use crate::schedule::Schedule;
+use crate::sync::SyncConfig;
+use crate::webhooks::WebhookConfig;
+
+use serde::Deserialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Settings read from the TOML config file, for anything too involved for a
+/// command line option.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub schedule: Option<Schedule>,
+    pub idle: Option<IdleConfig>,
+    pub hooks: HooksConfig,
+    pub dnd: Option<DndConfig>,
+    pub block: Option<BlockConfig>,
+    pub sync: Option<SyncConfig>,
+    pub http: Option<HttpConfig>,
+    pub mqtt: Option<MqttConfig>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub exit: ExitConfig,
+}
+
+impl Config {
+    pub fn default_path() -> PathBuf {
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .unwrap_or_else(env::temp_dir);
+        config_dir.join("tomatina").join("config.toml")
+    }
+
+    /// Reads the config, falling back to the defaults if the file doesn't exist.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(Error::io("read the config")(e)),
+        };
+        let invalid = |e: String| Error::Config(format!("{}: {}", path.display(), e));
+        let config: Self = toml::from_str(&contents).map_err(|e| invalid(e.to_string()))?;
+        if let Some(mqtt) = &config.mqtt {
+            mqtt.check().map_err(invalid)?;
+        }
+        config.exit.check().map_err(invalid)?;
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_config_uses_defaults() {
+        let config = Config::load(Path::new("/nonexistent/tomatina.toml")).unwrap();
+        assert!(config.schedule.is_none());
+    }
+
+    #[test]
+    fn test_config_reads_schedule() {
+        let config: Config = toml::from_str(
+            r#"
+            [schedule]
+            mon = "09:00-17:00"
+            outside = "end"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.schedule.unwrap().outside,
+            crate::schedule::OffHours::End
+        );
+    }
+
+    #[test]
+    fn test_config_reads_exit_settings() {
+        let config: Config = toml::from_str(
+            r#"
+            [exit]
+            color = [0, 0, 32]
+            restore = { released = [255, 0, 0], pressed = [0, 255, 0], primary = [0x70, 0x17] }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.exit.color, crate::button::Color(0, 0, 32));
+        let restore = config.exit.restore.unwrap();
+        assert_eq!(restore.pressed, crate::button::Color(0, 255, 0));
+        assert_eq!(restore.primary, vec![0x70, 0x17]);
+        assert!(restore.secondary.is_empty());
+    }
+}
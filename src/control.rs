@@ -0,0 +1,387 @@
+use crate::runtime;
+use crate::sync::Update;
+
+use libc::mkfifo;
+use log::warn;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Read, Result, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::{FileTypeExt, OpenOptionsExt};
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ButtonPress {
+    Primary,
+    Secondary,
+}
+
+/// Something written to the pipe. Button presses arrive as a bare `1` or `2`;
+/// anything else is a newline-terminated command such as `task write report`.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Press(ButtonPress),
+    SetTask(Option<String>),
+    /// Pauses the running phase, or resumes it if it's already paused.
+    Pause,
+    /// Where the leader of a shared session is up to. Only sent from within
+    /// the process.
+    Follow(Update),
+    /// Rereads the config file. Only sent from within the process.
+    Reload,
+    /// Tidies up and exits. Only sent from within the process.
+    Shutdown,
+}
+
+impl Command {
+    fn encode(&self) -> String {
+        match self {
+            Command::Press(ButtonPress::Primary) => "1".to_string(),
+            Command::Press(ButtonPress::Secondary) => "2".to_string(),
+            Command::SetTask(Some(task)) => format!("task {}\n", task.replace('\n', " ")),
+            Command::SetTask(None) => "task\n".to_string(),
+            Command::Pause => "pause\n".to_string(),
+            Command::Follow(_) | Command::Reload | Command::Shutdown => {
+                unreachable!("{:?} isn't sent over the pipe", self)
+            }
+        }
+    }
+}
+
+/// The named pipe a running tomatina takes commands on.
+pub fn fifo_path() -> PathBuf {
+    runtime::dir().join("tomatina.fifo")
+}
+
+/// Sends a command to a running tomatina.
+pub fn send(command: &Command) -> Result<()> {
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(fifo_path())
+        .map_err(|e| match e.raw_os_error() {
+            Some(libc::ENXIO) | Some(libc::ENOENT) => {
+                io::Error::new(io::ErrorKind::NotFound, "tomatina is not running")
+            }
+            _ => e,
+        })?;
+    file.write_all(command.encode().as_bytes())
+}
+
+/// Hands commands to the main loop from other threads.
+#[derive(Clone)]
+pub struct Sender {
+    commands: mpsc::Sender<Command>,
+    wake: Arc<UnixStream>,
+}
+
+impl Sender {
+    pub fn send(&self, command: Command) {
+        if self.commands.send(command).is_ok() {
+            // a full socket already has a wakeup waiting in it
+            let _ = (&*self.wake).write(&[0]);
+        }
+    }
+}
+
+pub struct ButtonSignal {
+    /// The named pipe, which only tests go without.
+    file: Option<fs::File>,
+    /// Where the named pipe is, so it can be removed once we're done with it.
+    path: Option<PathBuf>,
+    parser: Parser,
+    commands: VecDeque<Command>,
+    /// Read end of the socket `Sender`s write to after queueing a command.
+    wake: UnixStream,
+    receiver: mpsc::Receiver<Command>,
+    sender: Sender,
+}
+
+impl ButtonSignal {
+    /// Creates the named pipe, replacing one left behind by an earlier run, or
+    /// a file left by a workflow that wrote to it while we weren't running.
+    /// Only the holder of the instance lock should call this.
+    pub fn create() -> Result<Self> {
+        // nobody else can put files in the runtime directory
+        Self::create_at(&fifo_path(), true)
+    }
+
+    fn create_at(path: &Path, replace_file: bool) -> Result<Self> {
+        let context = |e: io::Error| io::Error::new(e.kind(), format!("{}: {}", path.display(), e));
+        match fs::symlink_metadata(path) {
+            Ok(metadata)
+                if metadata.file_type().is_fifo() || (replace_file && metadata.is_file()) =>
+            {
+                fs::remove_file(path).map_err(context)?
+            }
+            Ok(_) => {
+                return Err(context(io::Error::new(
+                    io::ErrorKind::AlreadyExists,
+                    "exists and isn't a named pipe",
+                )))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(context(e)),
+        }
+        let path_str = CString::new(path.as_os_str().as_bytes())?;
+        if unsafe { mkfifo(path_str.as_ptr(), 0o600) } != 0 {
+            return Err(context(io::Error::last_os_error()));
+        }
+        // Holding the write end open as well stops poll from reporting POLLHUP
+        // continuously once a writer has come and gone.
+        let file = fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(path)
+            .map_err(context)?;
+        let mut signal = Self::with_file(Some(file))?;
+        signal.path = Some(path.to_path_buf());
+        Ok(signal)
+    }
+
+    /// A signal that only takes commands from within the process.
+    #[cfg(test)]
+    pub fn in_process() -> Result<Self> {
+        Self::with_file(None)
+    }
+
+    fn with_file(file: Option<fs::File>) -> Result<Self> {
+        let (wake, wake_writer) = UnixStream::pair()?;
+        wake.set_nonblocking(true)?;
+        wake_writer.set_nonblocking(true)?;
+        let (sender, receiver) = mpsc::channel();
+        Ok(Self {
+            file,
+            path: None,
+            parser: Parser::default(),
+            commands: VecDeque::new(),
+            wake,
+            receiver,
+            sender: Sender {
+                commands: sender,
+                wake: Arc::new(wake_writer),
+            },
+        })
+    }
+
+    pub fn sender(&self) -> Sender {
+        self.sender.clone()
+    }
+
+    /// Blocks until a command arrives or `timeout` elapses. A `timeout` of `None`
+    /// waits indefinitely.
+    pub fn wait(&mut self, timeout: Option<Duration>) -> Result<Option<Command>> {
+        self.commands.extend(self.receiver.try_iter());
+        if let Some(command) = self.commands.pop_front() {
+            return Ok(Some(command));
+        }
+        let mut fds: Vec<libc::pollfd> = self
+            .file
+            .iter()
+            .map(AsRawFd::as_raw_fd)
+            .chain(Some(self.wake.as_raw_fd()))
+            .map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            })
+            .collect();
+        let timeout_ms = timeout.map_or(-1, |t| {
+            // round up so we never wake before a deadline
+            t.as_nanos()
+                .div_ceil(1_000_000)
+                .min(libc::c_int::MAX as u128) as libc::c_int
+        });
+        let result = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, timeout_ms) };
+        if result < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() != io::ErrorKind::Interrupted {
+                return Err(err);
+            }
+            return Ok(None);
+        }
+        if result == 0 {
+            return Ok(None);
+        }
+        let (wake, fifo) = fds.split_last().unwrap();
+        if fifo.iter().any(|fd| fd.revents != 0) {
+            self.poll()?;
+        }
+        if wake.revents != 0 {
+            let mut buf = [0; 64];
+            while let Ok(1..) = (&self.wake).read(&mut buf) {}
+            self.commands.extend(self.receiver.try_iter());
+        }
+        Ok(self.commands.pop_front())
+    }
+
+    fn poll(&mut self) -> Result<()> {
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+        let mut buf: [u8; 256] = [0; 256];
+        let read = match file.read(&mut buf) {
+            Ok(read) => read,
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => 0,
+            Err(e) => return Err(e),
+        };
+        self.commands.extend(self.parser.feed(&buf[..read]));
+        Ok(())
+    }
+}
+
+impl Drop for ButtonSignal {
+    fn drop(&mut self) {
+        if let Some(path) = &self.path {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Splits the byte stream from the pipe into commands, holding on to partial
+/// lines until the rest arrives.
+#[derive(Default)]
+struct Parser {
+    line: Vec<u8>,
+}
+
+impl Parser {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<Command> {
+        let mut commands = Vec::new();
+        for &byte in bytes {
+            if !self.line.is_empty() {
+                if byte == b'\n' {
+                    let line = String::from_utf8_lossy(&self.line).into_owned();
+                    self.line.clear();
+                    commands.extend(parse_line(&line));
+                } else {
+                    self.line.push(byte);
+                }
+                continue;
+            }
+            match byte {
+                b'1' => commands.push(Command::Press(ButtonPress::Primary)),
+                b'2' => commands.push(Command::Press(ButtonPress::Secondary)),
+                b'\n' | b'\r' | b' ' => {}
+                _ => self.line.push(byte),
+            }
+        }
+        commands
+    }
+}
+
+fn parse_line(line: &str) -> Option<Command> {
+    let line = line.trim();
+    let (name, arg) = match line.find(' ') {
+        Some(i) => (&line[..i], line[i + 1..].trim()),
+        None => (line, ""),
+    };
+    match name {
+        "task" if arg.is_empty() => Some(Command::SetTask(None)),
+        "task" => Some(Command::SetTask(Some(arg.to_string()))),
+        "pause" => Some(Command::Pause),
+        _ => {
+            warn!("unexpected command: {}", line);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_replaces_a_stale_pipe_but_nothing_else() {
+        let path = std::env::temp_dir().join(format!("tomatina-{}.fifo", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let stale = ButtonSignal::create_at(&path, false).unwrap();
+        drop(ButtonSignal::create_at(&path, false).unwrap());
+        // dropping removes the pipe, even if it has been replaced since
+        assert!(!path.exists());
+        drop(stale);
+        fs::write(&path, "notes").unwrap();
+        let err = ButtonSignal::create_at(&path, false).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "notes");
+        fs::remove_file(&path).unwrap();
+        let err = ButtonSignal::create_at(Path::new("/nonexistent/tomatina.fifo"), false)
+            .err()
+            .unwrap();
+        assert!(err.to_string().starts_with("/nonexistent/tomatina.fifo: "));
+    }
+
+    #[test]
+    fn test_create_in_the_runtime_directory_replaces_a_written_file() {
+        let path = std::env::temp_dir().join(format!("tomatina-{}-file.fifo", std::process::id()));
+        // what `echo -n 1 > tomatina.fifo` leaves when nothing's listening
+        fs::write(&path, "1").unwrap();
+        let signal = ButtonSignal::create_at(&path, true).unwrap();
+        assert!(fs::symlink_metadata(&path).unwrap().file_type().is_fifo());
+        drop(signal);
+        fs::create_dir(&path).unwrap();
+        let err = ButtonSignal::create_at(&path, true).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        fs::remove_dir(&path).unwrap();
+    }
+
+    #[test]
+    fn test_parser_reads_bare_button_codes() {
+        let mut parser = Parser::default();
+        assert_eq!(
+            parser.feed(b"12\n1"),
+            vec![
+                Command::Press(ButtonPress::Primary),
+                Command::Press(ButtonPress::Secondary),
+                Command::Press(ButtonPress::Primary),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parser_reads_task_commands_split_across_reads() {
+        let mut parser = Parser::default();
+        assert_eq!(
+            parser.feed(b"1task write "),
+            vec![Command::Press(ButtonPress::Primary)]
+        );
+        assert_eq!(
+            parser.feed(b"report 2\ntask\n"),
+            vec![
+                Command::SetTask(Some("write report 2".to_string())),
+                Command::SetTask(None),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_encoded_commands_parse_back() {
+        let commands = [
+            Command::Press(ButtonPress::Primary),
+            Command::SetTask(Some("a\nb".to_string())),
+            Command::SetTask(None),
+            Command::Pause,
+            Command::Press(ButtonPress::Secondary),
+        ];
+        let encoded: String = commands.iter().map(Command::encode).collect();
+        assert_eq!(
+            Parser::default().feed(encoded.as_bytes()),
+            vec![
+                Command::Press(ButtonPress::Primary),
+                Command::SetTask(Some("a b".to_string())),
+                Command::SetTask(None),
+                Command::Pause,
+                Command::Press(ButtonPress::Secondary),
+            ]
+        );
+    }
+}
@@ -0,0 +1,237 @@
+use crate::control::{ButtonPress, Command, Sender};
+
+use log::debug;
+use std::env;
+use std::ffi::OsString;
+use std::io::{self, Read};
+#[cfg(target_os = "linux")]
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::io::IntoRawFd;
+use std::os::unix::net::{SocketAddr, UnixDatagram, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::thread;
+
+/// Signals to exit cleanly on, whether run by hand or as a daemon.
+const EXIT_SIGNALS: [libc::c_int; 2] = [libc::SIGTERM, libc::SIGINT];
+/// Signals that only a daemon acts on.
+const DAEMON_SIGNALS: [libc::c_int; 2] = [libc::SIGHUP, libc::SIGUSR1];
+
+/// Options that take a path, which a unit has to give in full.
+const PATH_OPTIONS: [&str; 4] = ["--config", "--history", "--todo", "--log-file"];
+
+/// Write end of the socket signal handlers report to.
+static SIGNAL_SOCKET: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" fn on_signal(signal: libc::c_int) {
+    let byte = signal as u8;
+    // only async-signal-safe calls in here
+    unsafe {
+        libc::write(
+            SIGNAL_SOCKET.load(Ordering::Relaxed),
+            &byte as *const u8 as *const libc::c_void,
+            1,
+        );
+    }
+}
+
+/// Turns SIGTERM and SIGINT into a shutdown, handed to `inbox`, and for a
+/// `daemon` SIGHUP into a config reload and SIGUSR1 into a primary press.
+pub fn forward_signals(inbox: Sender, daemon: bool) -> io::Result<()> {
+    if daemon {
+        forward(&[EXIT_SIGNALS, DAEMON_SIGNALS].concat(), inbox)
+    } else {
+        forward(&EXIT_SIGNALS, inbox)
+    }
+}
+
+fn forward(signals: &[libc::c_int], inbox: Sender) -> io::Result<()> {
+    let (mut reader, writer) = UnixStream::pair()?;
+    // a handler must never block, even if the reader falls behind
+    writer.set_nonblocking(true)?;
+    // the handlers may write to it for as long as the process runs
+    SIGNAL_SOCKET.store(writer.into_raw_fd(), Ordering::Relaxed);
+    for &signal in signals {
+        let mut action: libc::sigaction = unsafe { std::mem::zeroed() };
+        action.sa_sigaction = on_signal as *const () as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESTART;
+        if unsafe { libc::sigaction(signal, &action, std::ptr::null_mut()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    thread::spawn(move || {
+        let mut buf = [0; 1];
+        while let Ok(1) = reader.read(&mut buf) {
+            let signal = libc::c_int::from(buf[0]);
+            debug!("Received signal {}", signal);
+            inbox.send(match signal {
+                libc::SIGHUP => Command::Reload,
+                libc::SIGUSR1 => Command::Press(ButtonPress::Primary),
+                _ => Command::Shutdown,
+            });
+        }
+    });
+    Ok(())
+}
+
+/// Tells systemd how we're getting on, if it started us with `Type=notify`.
+pub fn notify(state: &str) {
+    if let Some(socket) = env::var_os("NOTIFY_SOCKET") {
+        if let Err(e) = send_notification(&socket, state) {
+            debug!("failed to notify systemd: {}", e);
+        }
+    }
+}
+
+fn send_notification(socket: &OsString, state: &str) -> io::Result<()> {
+    let socket = socket.to_string_lossy();
+    let addr = match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "abstract sockets are only on Linux",
+            ))
+        }
+        None => SocketAddr::from_pathname(&*socket)?,
+    };
+    UnixDatagram::unbound()?.send_to_addr(state.as_bytes(), &addr)?;
+    Ok(())
+}
+
+/// A systemd user unit that runs `exe` with `args`.
+pub fn unit(exe: &str, args: &[String]) -> String {
+    let exec_start: Vec<String> = Some(exe)
+        .into_iter()
+        .chain(args.iter().map(String::as_str))
+        .map(quote)
+        .collect();
+    format!(
+        "[Unit]\n\
+         Description=Pomodoro timer for a USBButton\n\
+         \n\
+         [Service]\n\
+         Type=notify\n\
+         ExecStart={}\n\
+         ExecReload=/bin/kill -HUP $MAINPID\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        exec_start.join(" ")
+    )
+}
+
+/// `args` with the paths given to options made absolute against `dir`, as
+/// systemd starts services in `/` rather than wherever the unit was made.
+pub fn absolute_paths(args: &[String], dir: &Path) -> Vec<String> {
+    // going through the components drops any `.` in between
+    let absolute = |path: &str| {
+        let path: PathBuf = dir.join(path).components().collect();
+        path.to_string_lossy().into_owned()
+    };
+    let mut takes_path = false;
+    args.iter()
+        .map(|arg| {
+            if std::mem::take(&mut takes_path) {
+                return absolute(arg);
+            }
+            if PATH_OPTIONS.contains(&arg.as_str()) {
+                takes_path = true;
+            } else if let Some((option, path)) = arg.split_once('=') {
+                if PATH_OPTIONS.contains(&option) {
+                    return format!("{}={}", option, absolute(path));
+                }
+            }
+            arg.clone()
+        })
+        .collect()
+}
+
+/// Escapes a word for a unit file's command line.
+fn quote(word: &str) -> String {
+    let escaped = word
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    if escaped.is_empty() || escaped.contains(char::is_whitespace) || escaped != word {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ButtonSignal;
+    use std::fs;
+    use std::time::Duration;
+
+    #[test]
+    fn test_signals_become_commands() {
+        let mut signal = ButtonSignal::in_process().unwrap();
+        forward(&[libc::SIGUSR1], signal.sender()).unwrap();
+        unsafe { libc::raise(libc::SIGUSR1) };
+        let mut command = None;
+        // an interrupted wait returns early with nothing
+        for _ in 0..2 {
+            command = signal.wait(Some(Duration::from_secs(5))).unwrap();
+            if command.is_some() {
+                break;
+            }
+        }
+        assert_eq!(command, Some(Command::Press(ButtonPress::Primary)));
+    }
+
+    #[test]
+    fn test_notification_reaches_the_socket() {
+        let path = env::temp_dir().join(format!("tomatina-notify-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).unwrap();
+        send_notification(&path.clone().into_os_string(), "READY=1").unwrap();
+        let mut buf = [0; 16];
+        let len = socket.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..len], b"READY=1");
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_unit_quotes_arguments() {
+        let args = ["--task".to_string(), "50% done".to_string()];
+        let unit = unit("/usr/bin/tomatina", &args);
+        assert!(unit.contains("\nExecStart=/usr/bin/tomatina --task \"50%% done\"\n"));
+        assert!(unit.contains("\nType=notify\n"));
+    }
+
+    #[test]
+    fn test_unit_paths_are_made_absolute() {
+        let args: Vec<String> = [
+            "--config",
+            "./x.toml",
+            "--todo=todo.txt",
+            "--log-file",
+            "/var/log/tomatina.log",
+            "--task",
+            "todo.txt",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        assert_eq!(
+            absolute_paths(&args, Path::new("/home/me")),
+            vec![
+                "--config",
+                "/home/me/x.toml",
+                "--todo=/home/me/todo.txt",
+                "--log-file",
+                "/var/log/tomatina.log",
+                "--task",
+                "todo.txt",
+            ]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+use crate::shell;
+
+use serde::Deserialize;
+use std::io;
+use std::process::Command;
+#[cfg(test)]
+use std::{cell::RefCell, rc::Rc};
+
+const GNOME_SCHEMA: &str = "org.gnome.desktop.notifications";
+const MAKO_MODE: &str = "do-not-disturb";
+
+/// Suppresses desktop notifications while working.
+pub trait DoNotDisturb {
+    fn enable(&mut self) -> io::Result<()>;
+
+    /// Puts notifications back the way they were before `enable`.
+    fn restore(&mut self) -> io::Result<()>;
+}
+
+/// GNOME Shell, by turning off notification banners.
+#[derive(Default)]
+pub struct Gnome {
+    show_banners: Option<String>,
+}
+
+impl DoNotDisturb for Gnome {
+    fn enable(&mut self) -> io::Result<()> {
+        let current =
+            shell::output(Command::new("gsettings").args(["get", GNOME_SCHEMA, "show-banners"]))?;
+        gsettings_set("false")?;
+        self.show_banners = Some(current.trim().to_string());
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        match self.show_banners.take() {
+            Some(value) => gsettings_set(&value),
+            None => Ok(()),
+        }
+    }
+}
+
+fn gsettings_set(value: &str) -> io::Result<()> {
+    shell::output(Command::new("gsettings").args(["set", GNOME_SCHEMA, "show-banners", value]))
+        .map(drop)
+}
+
+/// The mako notification daemon, by switching on its `do-not-disturb` mode.
+/// That mode needs a `[mode=do-not-disturb]` section hiding notifications in
+/// mako's config.
+#[derive(Default)]
+pub struct Mako {
+    added: bool,
+}
+
+impl DoNotDisturb for Mako {
+    fn enable(&mut self) -> io::Result<()> {
+        let modes = shell::output(Command::new("makoctl").arg("mode"))?;
+        if modes.lines().any(|mode| mode.trim() == MAKO_MODE) {
+            return Ok(());
+        }
+        shell::output(Command::new("makoctl").args(["mode", "-a", MAKO_MODE]))?;
+        self.added = true;
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        if !self.added {
+            return Ok(());
+        }
+        shell::output(Command::new("makoctl").args(["mode", "-r", MAKO_MODE]))?;
+        self.added = false;
+        Ok(())
+    }
+}
+
+/// The dunst notification daemon, by pausing it.
+#[derive(Default)]
+pub struct Dunst {
+    paused: Option<String>,
+}
+
+impl DoNotDisturb for Dunst {
+    fn enable(&mut self) -> io::Result<()> {
+        let current = shell::output(Command::new("dunstctl").arg("is-paused"))?;
+        shell::output(Command::new("dunstctl").args(["set-paused", "true"]))?;
+        self.paused = Some(current.trim().to_string());
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        match self.paused.take() {
+            Some(value) => {
+                shell::output(Command::new("dunstctl").args(["set-paused", &value])).map(drop)
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    Gnome,
+    Mako,
+    Dunst,
+}
+
+/// The `[dnd]` section of the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DndConfig {
+    pub backend: Backend,
+}
+
+impl DndConfig {
+    pub fn backend(&self) -> Box<dyn DoNotDisturb> {
+        match self.backend {
+            Backend::Gnome => Box::<Gnome>::default(),
+            Backend::Mako => Box::<Mako>::default(),
+            Backend::Dunst => Box::<Dunst>::default(),
+        }
+    }
+}
+
+/// Records whether do-not-disturb is on after each call. Clones share the
+/// same record.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeDnd {
+    pub calls: Rc<RefCell<Vec<bool>>>,
+}
+
+#[cfg(test)]
+impl DoNotDisturb for FakeDnd {
+    fn enable(&mut self) -> io::Result<()> {
+        self.calls.borrow_mut().push(true);
+        Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        self.calls.borrow_mut().push(false);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_reads_backend() {
+        let config: DndConfig = toml::from_str("backend = \"mako\"").unwrap();
+        assert_eq!(config.backend, Backend::Mako);
+        assert!(toml::from_str::<DndConfig>("backend = \"windows\"").is_err());
+    }
+}
@@ -0,0 +1,30 @@
+use crate::button::{self, USBBTN_INTERFACE};
+use crate::error::{Error, Result};
+
+use rusb::Context;
+
+/// Works through what tomatina needs to use the button, printing each step
+/// that checks out and failing with the first that doesn't.
+pub fn run(context: &Context) -> Result<()> {
+    let device = button::find(context)?;
+    println!(
+        "ok    found the USBButton on bus {:03} device {:03}",
+        device.bus_number(),
+        device.address()
+    );
+    let handle = device.open().map_err(Error::from)?;
+    println!("ok    can open the USBButton");
+    // the driver can only be asked after with an open handle
+    match handle.kernel_driver_active(USBBTN_INTERFACE as u8) {
+        Ok(false) => println!("ok    no kernel driver on interface {}", USBBTN_INTERFACE),
+        Ok(true) => println!(
+            "ok    a kernel driver, usually usbhid, is bound to interface {}; if setting \
+             colors fails, try --detach-kernel-driver",
+            USBBTN_INTERFACE
+        ),
+        // not every platform can tell
+        Err(rusb::Error::NotSupported) => {}
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
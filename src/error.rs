@@ -0,0 +1,117 @@
+use std::fmt;
+use std::io;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Everything that can stop tomatina, each with its own exit code so scripts
+/// and service managers can tell them apart.
+#[derive(Debug)]
+pub enum Error {
+    /// There's no USBButton to be seen.
+    NoButton,
+    /// Talking to the button failed.
+    Usb(rusb::Error),
+    /// The kernel's driver wouldn't let go of the button.
+    KernelDriver(rusb::Error),
+    Io(io::Error),
+    /// The config file or an option doesn't make sense.
+    Config(String),
+    /// Another tomatina has the button.
+    AlreadyRunning(String),
+}
+
+impl Error {
+    /// Adds what we were trying to do to an IO error, for use with `map_err`.
+    pub fn io(doing: &str) -> impl FnOnce(io::Error) -> Self + '_ {
+        move |e| {
+            Error::Io(io::Error::new(
+                e.kind(),
+                format!("failed to {}: {}", doing, e),
+            ))
+        }
+    }
+
+    /// Exit codes follow sysexits.h.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::NoButton => 69,
+            Error::Usb(rusb::Error::Access) => 77,
+            Error::Usb(_) => 69,
+            Error::KernelDriver(rusb::Error::Access) => 77,
+            Error::KernelDriver(_) => 69,
+            Error::Io(_) => 74,
+            Error::Config(_) => 78,
+            Error::AlreadyRunning(_) => 75,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // only Linux has udev to set up
+            Error::NoButton => {
+                write!(f, "no USBButton found: check it's plugged in")?;
+                if cfg!(target_os = "linux") {
+                    write!(f, " and that udev lets you use it")?;
+                }
+                Ok(())
+            }
+            Error::Usb(rusb::Error::Access) => {
+                write!(f, "not allowed to use the USBButton")?;
+                if cfg!(target_os = "linux") {
+                    write!(
+                        f,
+                        ": `sudo tomatina setup linux` lets you use it without root"
+                    )?;
+                }
+                Ok(())
+            }
+            Error::Usb(rusb::Error::NoDevice) => write!(f, "the USBButton was unplugged"),
+            Error::Usb(rusb::Error::Busy) => {
+                write!(f, "the USBButton is in use by another program")
+            }
+            Error::Usb(e) => write!(f, "failed to talk to the USBButton: {}", e),
+            Error::KernelDriver(e) => write!(
+                f,
+                "failed to detach the kernel driver from the USBButton or give it back ({}): \
+                 run as a user allowed to, or leave out --detach-kernel-driver",
+                e
+            ),
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Config(message) => write!(f, "invalid config: {}", message),
+            Error::AlreadyRunning(message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<rusb::Error> for Error {
+    fn from(e: rusb::Error) -> Self {
+        Error::Usb(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_io_errors_say_what_failed() {
+        let error = Error::io("read the history")(io::Error::from(io::ErrorKind::NotFound));
+        assert_eq!(
+            error.to_string(),
+            "failed to read the history: entity not found"
+        );
+        assert_eq!(error.exit_code(), 74);
+        assert_eq!(Error::Usb(rusb::Error::Access).exit_code(), 77);
+        assert_eq!(Error::KernelDriver(rusb::Error::Access).exit_code(), 77);
+    }
+}
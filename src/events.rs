@@ -0,0 +1,81 @@
+use crate::control::ButtonPress;
+use crate::tracker::State;
+
+use serde::Serialize;
+use std::sync::{mpsc, Arc, Mutex};
+
+/// Something that happened in the main loop, for anything watching over the
+/// network.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    StateChanged {
+        from: State,
+        to: State,
+        intervals: u64,
+    },
+    /// Sent at each whole minute remaining in a phase.
+    Tick {
+        state: State,
+        remaining_secs: u64,
+    },
+    /// The phase is about to run out.
+    Warning {
+        state: State,
+        remaining_secs: u64,
+    },
+    PressReceived {
+        button: ButtonPress,
+    },
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::StateChanged { .. } => "state_changed",
+            Event::Tick { .. } => "tick",
+            Event::Warning { .. } => "warning",
+            Event::PressReceived { .. } => "press_received",
+        }
+    }
+}
+
+/// Hands events to every subscriber, forgetting those that have gone away.
+#[derive(Clone, Default)]
+pub struct Broadcaster {
+    subscribers: Arc<Mutex<Vec<mpsc::Sender<Event>>>>,
+}
+
+impl Broadcaster {
+    pub fn subscribe(&self) -> mpsc::Receiver<Event> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    pub fn send(&self, event: Event) {
+        self.subscribers
+            .lock()
+            .unwrap()
+            .retain(|subscriber| subscriber.send(event.clone()).is_ok());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_reach_every_current_subscriber() {
+        let events = Broadcaster::default();
+        let first = events.subscribe();
+        let second = events.subscribe();
+        drop(second);
+        let event = Event::PressReceived {
+            button: ButtonPress::Primary,
+        };
+        events.send(event.clone());
+        assert_eq!(first.try_recv(), Ok(event));
+        assert_eq!(events.subscribers.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,341 @@
+use crate::history::{Outcome, Record};
+use crate::tracker::State;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use serde::Serialize;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Format {
+    Csv,
+    Json,
+    Ics,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ics" => Ok(Format::Ics),
+            _ => Err(format!("unknown export format: {}", s)),
+        }
+    }
+}
+
+/// Records that started between `from` and `to` inclusive, as dates in `tz`.
+/// Records with times too far out to be dates are left out.
+pub fn between<Tz: TimeZone>(
+    records: &[Record],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    tz: &Tz,
+) -> Vec<Record> {
+    records
+        .iter()
+        .filter(|record| {
+            let date = match (
+                to_datetime(record.started, tz),
+                to_datetime(record.ended, tz),
+            ) {
+                (Ok(started), Ok(_)) => started.date_naive(),
+                _ => return false,
+            };
+            from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to)
+        })
+        .cloned()
+        .collect()
+}
+
+/// Writes records with times in `tz`. Calendars only get the work intervals.
+pub fn write<W: Write, Tz: TimeZone>(
+    out: &mut W,
+    format: Format,
+    records: &[Record],
+    tz: &Tz,
+) -> io::Result<()>
+where
+    Tz::Offset: fmt::Display,
+{
+    match format {
+        Format::Csv => write_csv(out, records, tz),
+        Format::Json => write_json(out, records, tz),
+        Format::Ics => write_ics(out, records),
+    }
+}
+
+#[derive(Serialize)]
+struct Row<'a> {
+    state: State,
+    started: String,
+    ended: String,
+    duration_secs: u64,
+    outcome: Outcome,
+    task: Option<&'a str>,
+}
+
+impl<'a> Row<'a> {
+    fn new<Tz: TimeZone>(record: &'a Record, tz: &Tz) -> io::Result<Self>
+    where
+        Tz::Offset: fmt::Display,
+    {
+        Ok(Self {
+            state: record.state,
+            started: to_datetime(record.started, tz)?.to_rfc3339(),
+            ended: to_datetime(record.ended, tz)?.to_rfc3339(),
+            duration_secs: record.duration().as_secs(),
+            outcome: record.outcome,
+            task: record.task.as_deref(),
+        })
+    }
+}
+
+fn write_csv<W: Write, Tz: TimeZone>(out: &mut W, records: &[Record], tz: &Tz) -> io::Result<()>
+where
+    Tz::Offset: fmt::Display,
+{
+    writeln!(out, "state,started,ended,duration_secs,outcome,task")?;
+    for record in records {
+        let row = Row::new(record, tz)?;
+        writeln!(
+            out,
+            "{:?},{},{},{},{},{}",
+            row.state,
+            row.started,
+            row.ended,
+            row.duration_secs,
+            match row.outcome {
+                Outcome::Completed => "completed",
+                Outcome::Interrupted => "interrupted",
+            },
+            csv_field(row.task.unwrap_or("")),
+        )?;
+    }
+    Ok(())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn write_json<W: Write, Tz: TimeZone>(out: &mut W, records: &[Record], tz: &Tz) -> io::Result<()>
+where
+    Tz::Offset: fmt::Display,
+{
+    for record in records {
+        serde_json::to_writer(&mut *out, &Row::new(record, tz)?)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn write_ics<W: Write>(out: &mut W, records: &[Record]) -> io::Result<()> {
+    write_ics_line(out, "BEGIN:VCALENDAR")?;
+    write_ics_line(out, "VERSION:2.0")?;
+    write_ics_line(out, "PRODID:-//tomatina//EN")?;
+    for record in records.iter().filter(|r| r.state == State::Working) {
+        let mut summary = record
+            .task
+            .clone()
+            .unwrap_or_else(|| "Pomodoro".to_string());
+        if record.outcome == Outcome::Interrupted {
+            summary.push_str(" (interrupted)");
+        }
+        write_ics_line(out, "BEGIN:VEVENT")?;
+        write_ics_line(out, &format!("UID:{}@tomatina", record.started))?;
+        write_ics_line(out, &format!("DTSTAMP:{}", ics_time(record.ended)?))?;
+        write_ics_line(out, &format!("DTSTART:{}", ics_time(record.started)?))?;
+        write_ics_line(out, &format!("DTEND:{}", ics_time(record.ended)?))?;
+        write_ics_line(out, &format!("SUMMARY:{}", ics_text(&summary)))?;
+        write_ics_line(out, "END:VEVENT")?;
+    }
+    write_ics_line(out, "END:VCALENDAR")
+}
+
+/// Writes a content line, folded so that no line is longer than 75 octets,
+/// as RFC 5545 asks. Each continuation starts with a space.
+fn write_ics_line<W: Write>(out: &mut W, line: &str) -> io::Result<()> {
+    let mut rest = line;
+    let mut limit = 75;
+    loop {
+        let mut end = rest.len().min(limit);
+        // don't split a character
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        write!(out, "{}\r\n", &rest[..end])?;
+        rest = &rest[end..];
+        if rest.is_empty() {
+            return Ok(());
+        }
+        write!(out, " ")?;
+        limit = 74;
+    }
+}
+
+fn ics_time(secs: u64) -> io::Result<String> {
+    Ok(to_datetime(secs, &Utc)?
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string())
+}
+
+fn ics_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+}
+
+fn to_datetime<Tz: TimeZone>(secs: u64, tz: &Tz) -> io::Result<DateTime<Tz>> {
+    i64::try_from(secs)
+        .ok()
+        .and_then(|secs| tz.timestamp_opt(secs, 0).single())
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} seconds since 1970 is out of range", secs),
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 2020-09-13T12:26:40Z
+    const START: u64 = 1_600_000_000;
+
+    fn records() -> Vec<Record> {
+        vec![
+            Record {
+                state: State::Working,
+                started: START,
+                ended: START + 1500,
+                outcome: Outcome::Completed,
+                task: Some("write report, draft".to_string()),
+                paused: 0,
+            },
+            Record {
+                state: State::ShortBreak,
+                started: START + 1500,
+                ended: START + 1800,
+                outcome: Outcome::Completed,
+                task: None,
+                paused: 0,
+            },
+            Record {
+                state: State::Working,
+                started: START + 86_400,
+                ended: START + 86_400 + 600,
+                outcome: Outcome::Interrupted,
+                task: None,
+                paused: 0,
+            },
+        ]
+    }
+
+    fn export(format: Format, records: &[Record]) -> String {
+        let mut out = Vec::new();
+        write(&mut out, format, records, &Utc).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn test_between_leaves_out_times_out_of_range() {
+        let mut records = records();
+        records[1].ended = u64::MAX;
+        assert_eq!(between(&records, None, None, &Utc).len(), 2);
+        assert!(write(&mut Vec::new(), Format::Csv, &records, &Utc).is_err());
+    }
+
+    #[test]
+    fn test_between_filters_by_start_date() {
+        let day = |d| NaiveDate::from_ymd_opt(2020, 9, d).unwrap();
+        assert_eq!(between(&records(), None, None, &Utc).len(), 3);
+        assert_eq!(between(&records(), Some(day(14)), None, &Utc).len(), 1);
+        assert_eq!(between(&records(), None, Some(day(13)), &Utc).len(), 2);
+        assert_eq!(
+            between(&records(), Some(day(12)), Some(day(12)), &Utc).len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_csv_export() {
+        assert_eq!(
+            export(Format::Csv, &records()[..2]),
+            "state,started,ended,duration_secs,outcome,task\n\
+             Working,2020-09-13T12:26:40+00:00,2020-09-13T12:51:40+00:00,1500,completed,\"write report, draft\"\n\
+             ShortBreak,2020-09-13T12:51:40+00:00,2020-09-13T12:56:40+00:00,300,completed,\n"
+        );
+    }
+
+    #[test]
+    fn test_json_export() {
+        assert_eq!(
+            export(Format::Json, &records()[1..2]),
+            "{\"state\":\"ShortBreak\",\"started\":\"2020-09-13T12:51:40+00:00\",\
+             \"ended\":\"2020-09-13T12:56:40+00:00\",\"duration_secs\":300,\
+             \"outcome\":\"completed\",\"task\":null}\n"
+        );
+    }
+
+    #[test]
+    fn test_ics_export_has_an_event_per_work_interval() {
+        assert_eq!(
+            export(Format::Ics, &records()),
+            "BEGIN:VCALENDAR\r\n\
+             VERSION:2.0\r\n\
+             PRODID:-//tomatina//EN\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:1600000000@tomatina\r\n\
+             DTSTAMP:20200913T125140Z\r\n\
+             DTSTART:20200913T122640Z\r\n\
+             DTEND:20200913T125140Z\r\n\
+             SUMMARY:write report\\, draft\r\n\
+             END:VEVENT\r\n\
+             BEGIN:VEVENT\r\n\
+             UID:1600086400@tomatina\r\n\
+             DTSTAMP:20200914T123640Z\r\n\
+             DTSTART:20200914T122640Z\r\n\
+             DTEND:20200914T123640Z\r\n\
+             SUMMARY:Pomodoro (interrupted)\r\n\
+             END:VEVENT\r\n\
+             END:VCALENDAR\r\n"
+        );
+    }
+
+    #[test]
+    fn test_ics_export_folds_long_lines() {
+        let record = Record {
+            task: Some(format!("{}é{}", "a".repeat(66), "b".repeat(80))),
+            ..records().remove(0)
+        };
+        let ics = export(Format::Ics, &[record]);
+        let summary: Vec<&str> = ics
+            .split("\r\n")
+            .skip_while(|line| !line.starts_with("SUMMARY:"))
+            .take_while(|line| *line != "END:VEVENT")
+            .collect();
+        assert_eq!(summary.len(), 3);
+        assert!(summary.iter().all(|line| line.len() <= 75));
+        // the two octets of é are kept together
+        assert_eq!(summary[0].len(), 74);
+        assert!(summary[1..].iter().all(|line| line.starts_with(' ')));
+        let unfolded: String = summary.iter().map(|line| line.trim_start()).collect();
+        assert_eq!(
+            unfolded,
+            format!("SUMMARY:{}é{}", "a".repeat(66), "b".repeat(80))
+        );
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::os::unix::fs::{self as unix_fs, MetadataExt};
+use std::path::Path;
+
+/// Replaces the contents of the file at `path` by writing them to a file
+/// alongside it and renaming that over it, so a crash or a full disk leaves
+/// either the old contents or the new, never a truncated file. The file keeps
+/// its permissions and, where we're allowed to, its owner.
+pub fn replace(path: &Path, contents: &str) -> io::Result<()> {
+    // rename over the file a symlink points to, not the symlink
+    let path = fs::canonicalize(path)?;
+    let metadata = fs::metadata(&path)?;
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(format!(".tomatina-{}", std::process::id()));
+    let tmp = path.with_file_name(name);
+    let result = File::create(&tmp).and_then(|mut file| {
+        file.write_all(contents.as_bytes())?;
+        file.set_permissions(metadata.permissions())?;
+        // only root can give a file away, and anyone else already owns it
+        let _ = unix_fs::fchown(&file, Some(metadata.uid()), Some(metadata.gid()));
+        file.sync_all()?;
+        fs::rename(&tmp, &path)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_replace_keeps_permissions_and_leaves_nothing_behind() {
+        let dir = std::env::temp_dir().join(format!("tomatina-files-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("todo.txt");
+        fs::write(&path, "old\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+        replace(&path, "new\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
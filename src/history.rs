@@ -0,0 +1,174 @@
+use crate::tracker::State;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Completed,
+    Interrupted,
+}
+
+/// A phase that has ended. Times are seconds since the Unix epoch. Records of
+/// pending states measure the overtime spent after the preceding phase ran out.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub state: State,
+    pub started: u64,
+    pub ended: u64,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub task: Option<String>,
+    /// Seconds of the phase spent paused.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub paused: u64,
+}
+
+impl Record {
+    /// Time spent in the phase, less any time paused.
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs(
+            self.ended
+                .saturating_sub(self.started)
+                .saturating_sub(self.paused),
+        )
+    }
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+/// An append-only log of records, stored as one JSON object per line.
+pub struct History {
+    path: PathBuf,
+}
+
+impl History {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn default_path() -> PathBuf {
+        let data_dir = env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+            .unwrap_or_else(env::temp_dir);
+        data_dir.join("tomatina").join("history.jsonl")
+    }
+
+    pub fn append(&self, record: &Record) -> io::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&self.path)?;
+        // start afresh after a line left partial by a crash
+        let mut last = [b'\n'];
+        if file.seek(SeekFrom::End(0))? > 0 {
+            file.seek(SeekFrom::End(-1))?;
+            file.read_exact(&mut last)?;
+        }
+        if last[0] != b'\n' {
+            writeln!(file)?;
+        }
+        let line = serde_json::to_string(record)?;
+        writeln!(file, "{}", line)
+    }
+
+    pub fn read(&self) -> io::Result<Vec<Record>> {
+        let file = match fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut records = Vec::new();
+        for (number, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            // a crash mid-append leaves a partial line, which shouldn't cost
+            // the rest of the history
+            match serde_json::from_str(&line) {
+                Ok(record) => records.push(record),
+                Err(e) => warn!(
+                    "Skipping line {} of {}: {}",
+                    number + 1,
+                    self.path.display(),
+                    e
+                ),
+            }
+        }
+        Ok(records)
+    }
+}
+
+pub fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_appended_records_can_be_read_back() {
+        let path = env::temp_dir().join(format!("tomatina-history-{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let history = History::new(path.clone());
+        assert_eq!(history.read().unwrap(), vec![]);
+        let records = vec![
+            Record {
+                state: State::Working,
+                started: 100,
+                ended: 200,
+                outcome: Outcome::Completed,
+                task: Some("write report".to_string()),
+                paused: 0,
+            },
+            Record {
+                state: State::ShortBreak,
+                started: 200,
+                ended: 250,
+                outcome: Outcome::Interrupted,
+                task: None,
+                paused: 20,
+            },
+        ];
+        for record in &records {
+            history.append(record).unwrap();
+        }
+        assert_eq!(history.read().unwrap(), records);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_truncated_record_is_skipped() {
+        let path = env::temp_dir().join(format!("tomatina-truncated-{}.jsonl", std::process::id()));
+        fs::write(
+            &path,
+            "{\"state\":\"Working\",\"started\":100,\"ended\":200,\"outcome\":\"completed\"}\n\
+             {\"state\":\"ShortBreak\",\"started\":200,\"en",
+        )
+        .unwrap();
+        let history = History::new(path.clone());
+        let records = history.read().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].ended, 200);
+        history.append(&records[0]).unwrap();
+        assert_eq!(history.read().unwrap().len(), 2);
+        fs::remove_file(path).unwrap();
+    }
+}
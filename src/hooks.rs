@@ -0,0 +1,144 @@
+use crate::tracker::State;
+
+use log::warn;
+use serde::Deserialize;
+use std::process::{Command, Stdio};
+use std::thread::{self, sleep, JoinHandle};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The `[hooks]` section of the config file: shell commands to run on entering
+/// each state.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HooksConfig {
+    pub on_enter_pending_work: Option<String>,
+    pub on_enter_working: Option<String>,
+    pub on_enter_pending_short_break: Option<String>,
+    pub on_enter_short_break: Option<String>,
+    pub on_enter_pending_long_break: Option<String>,
+    pub on_enter_long_break: Option<String>,
+    /// Hooks still running after this long are killed.
+    pub timeout_secs: u64,
+}
+
+impl Default for HooksConfig {
+    fn default() -> Self {
+        Self {
+            on_enter_pending_work: None,
+            on_enter_working: None,
+            on_enter_pending_short_break: None,
+            on_enter_short_break: None,
+            on_enter_pending_long_break: None,
+            on_enter_long_break: None,
+            timeout_secs: 10,
+        }
+    }
+}
+
+impl HooksConfig {
+    fn command(&self, state: State) -> Option<&str> {
+        match state {
+            State::PendingWork => self.on_enter_pending_work.as_deref(),
+            State::Working => self.on_enter_working.as_deref(),
+            State::PendingShortBreak => self.on_enter_pending_short_break.as_deref(),
+            State::ShortBreak => self.on_enter_short_break.as_deref(),
+            State::PendingLongBreak => self.on_enter_pending_long_break.as_deref(),
+            State::LongBreak => self.on_enter_long_break.as_deref(),
+        }
+    }
+
+    /// Starts the hook for entering `state`, if there is one, in the background.
+    /// Failures are logged rather than returned.
+    pub fn run(&self, previous: State, state: State, intervals: u64) -> Option<JoinHandle<()>> {
+        let command = self.command(state)?;
+        let env = [
+            ("TOMATINA_STATE", format!("{:?}", state)),
+            ("TOMATINA_PREV_STATE", format!("{:?}", previous)),
+            ("TOMATINA_INTERVALS", intervals.to_string()),
+        ];
+        Some(spawn(command, &env, Duration::from_secs(self.timeout_secs)))
+    }
+}
+
+fn spawn(command: &str, env: &[(&str, String)], timeout: Duration) -> JoinHandle<()> {
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(key, value)| (key, value)))
+        .stdin(Stdio::null())
+        .spawn();
+    let command = command.to_string();
+    thread::spawn(move || {
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                warn!("failed to run hook {:?}: {}", command, e);
+                return;
+            }
+        };
+        let deadline = Instant::now() + timeout;
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) if status.success() => return,
+                Ok(Some(status)) => {
+                    warn!("hook {:?} failed: {}", command, status);
+                    return;
+                }
+                Ok(None) if Instant::now() >= deadline => {
+                    warn!(
+                        "hook {:?} timed out after {:?}, killing it",
+                        command, timeout
+                    );
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                Ok(None) => sleep(POLL_INTERVAL),
+                Err(e) => {
+                    warn!("failed to wait for hook {:?}: {}", command, e);
+                    return;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_hook_sees_the_transition_in_its_environment() {
+        let path = std::env::temp_dir().join(format!("tomatina-hook-{}", std::process::id()));
+        let config = HooksConfig {
+            on_enter_working: Some(format!(
+                "echo $TOMATINA_PREV_STATE $TOMATINA_STATE $TOMATINA_INTERVALS > {}",
+                path.display()
+            )),
+            ..Default::default()
+        };
+        assert!(config.run(State::Working, State::ShortBreak, 1).is_none());
+        config
+            .run(State::PendingWork, State::Working, 3)
+            .unwrap()
+            .join()
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "PendingWork Working 3\n"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_hook_is_killed_after_the_timeout() {
+        let started = Instant::now();
+        spawn("sleep 5", &[], Duration::from_millis(100))
+            .join()
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
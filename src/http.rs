@@ -0,0 +1,392 @@
+use crate::control::{ButtonPress, Command, Sender};
+use crate::events::Broadcaster;
+use crate::history::History;
+use crate::status::Status;
+
+use log::{error, info, warn};
+use serde::Deserialize;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::Duration;
+
+const DASHBOARD: &str = include_str!("dashboard.html");
+/// Drop clients that take longer than this to send their request.
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Nothing we serve needs a bigger request than this, so don't read one.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024;
+/// Comment lines are sent to event streams this often so proxies don't give
+/// up on them, and so we notice clients that have gone.
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The `[http]` section of the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    pub listen: String,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        Self {
+            listen: "127.0.0.1:4300".to_string(),
+        }
+    }
+}
+
+struct Response {
+    status: &'static str,
+    content_type: &'static str,
+    body: String,
+}
+
+impl Response {
+    fn json(body: String) -> Self {
+        Self {
+            status: "200 OK",
+            content_type: "application/json",
+            body,
+        }
+    }
+
+    fn accepted() -> Self {
+        Self {
+            status: "202 Accepted",
+            ..Self::json("{}".to_string())
+        }
+    }
+
+    fn error(status: &'static str, message: &str) -> Self {
+        Self {
+            status,
+            ..Self::json(serde_json::json!({ "error": message }).to_string())
+        }
+    }
+}
+
+/// Serves the status and history as JSON, and takes commands, for anything on
+/// the network that wants to watch or drive the timer.
+pub struct Server {
+    addr: SocketAddr,
+}
+
+impl Server {
+    pub fn start(
+        config: &HttpConfig,
+        status_path: PathBuf,
+        history_path: PathBuf,
+        inbox: Sender,
+        events: Broadcaster,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(&config.listen)?;
+        let server = Self {
+            addr: listener.local_addr()?,
+        };
+        info!("Serving the dashboard on http://{}/", server.addr());
+        let routes = Arc::new(Routes {
+            hosts: allowed_hosts(&config.listen, server.addr),
+            status_path,
+            history: History::new(history_path),
+            events,
+        });
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let routes = routes.clone();
+                        let inbox = inbox.clone();
+                        thread::spawn(move || {
+                            if let Err(e) = serve(stream, &routes, &inbox) {
+                                warn!("failed to serve request: {}", e);
+                            }
+                        });
+                    }
+                    Err(e) => error!("failed to accept connection: {}", e),
+                }
+            }
+        });
+        Ok(server)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+struct Routes {
+    /// What the `Host` header may be.
+    hosts: Vec<String>,
+    status_path: PathBuf,
+    history: History,
+    events: Broadcaster,
+}
+
+impl Routes {
+    fn route(&self, method: &str, path: &str, inbox: &Sender) -> Response {
+        match (method, path) {
+            ("GET", "/") => Response {
+                content_type: "text/html; charset=utf-8",
+                body: DASHBOARD.to_string(),
+                ..Response::json(String::new())
+            },
+            ("GET", "/status") => match Status::read(&self.status_path) {
+                Ok(status) => Response::json(serde_json::to_string(&status).unwrap()),
+                Err(e) => Response::error("503 Service Unavailable", &e.to_string()),
+            },
+            ("GET", "/history") => match self.history.read() {
+                Ok(records) => Response::json(serde_json::to_string(&records).unwrap()),
+                Err(e) => Response::error("500 Internal Server Error", &e.to_string()),
+            },
+            ("POST", "/next") => {
+                inbox.send(Command::Press(ButtonPress::Primary));
+                Response::accepted()
+            }
+            ("POST", "/pause") => {
+                inbox.send(Command::Pause);
+                Response::accepted()
+            }
+            (_, "/")
+            | (_, "/status")
+            | (_, "/history")
+            | (_, "/events")
+            | (_, "/next")
+            | (_, "/pause") => Response::error("405 Method Not Allowed", "method not allowed"),
+            _ => Response::error("404 Not Found", "not found"),
+        }
+    }
+}
+
+fn serve(stream: TcpStream, routes: &Routes, inbox: &Sender) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new((&stream).take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method, target),
+        _ => return respond(&stream, &Response::error("400 Bad Request", "bad request")),
+    };
+    let mut content_length = 0;
+    let mut origin = None;
+    let mut host = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            let value = value.trim();
+            match name.trim().to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap_or(0),
+                "origin" => origin = Some(value.to_string()),
+                "host" => host = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+    // bodies aren't used, but read them so the client sees a clean close
+    io::copy(&mut reader.take(content_length), &mut io::sink())?;
+    // a page whose name was rebound to our address would otherwise be
+    // treated as our own
+    let host_allowed = host
+        .as_deref()
+        .is_some_and(|host| routes.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)));
+    if !host_allowed {
+        return respond(&stream, &Response::error("403 Forbidden", "unknown host"));
+    }
+    // browsers let any page post here without asking first, so only take
+    // commands from the dashboard itself or from outside a browser
+    if method == "POST" && !same_origin(origin.as_deref(), host.as_deref()) {
+        return respond(
+            &stream,
+            &Response::error("403 Forbidden", "cross-origin request"),
+        );
+    }
+    let path = target.split('?').next().unwrap_or(target);
+    if (method, path) == ("GET", "/events") {
+        return stream_events(&stream, &routes.events);
+    }
+    respond(&stream, &routes.route(method, path, inbox))
+}
+
+/// Sends events as they happen, as Server-Sent Events, until the client goes.
+fn stream_events(mut stream: &TcpStream, events: &Broadcaster) -> io::Result<()> {
+    let events = events.subscribe();
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\r\n"
+    )?;
+    loop {
+        match events.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(event) => write!(
+                stream,
+                "event: {}\ndata: {}\n\n",
+                event.name(),
+                serde_json::to_string(&event)?
+            )?,
+            Err(mpsc::RecvTimeoutError::Timeout) => write!(stream, ": keepalive\n\n")?,
+            Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+/// The names we answer to: the address we were told to listen on, the one we
+/// got, and localhost in its various forms, all with our port.
+fn allowed_hosts(listen: &str, addr: SocketAddr) -> Vec<String> {
+    let mut hosts = vec![listen.to_string(), addr.to_string()];
+    for name in &["localhost", "127.0.0.1", "[::1]"] {
+        hosts.push(format!("{}:{}", name, addr.port()));
+        // browsers leave the default port out
+        if addr.port() == 80 {
+            hosts.push(name.to_string());
+        }
+    }
+    hosts
+}
+
+/// Whether a request came from a page served by us, taking one without an
+/// `Origin` as not coming from a browser at all.
+fn same_origin(origin: Option<&str>, host: Option<&str>) -> bool {
+    match origin {
+        Some(origin) => host.is_some() && origin.split_once("://").map(|(_, rest)| rest) == host,
+        None => true,
+    }
+}
+
+fn respond(mut stream: &TcpStream, response: &Response) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.content_type,
+        response.body.len(),
+        response.body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ButtonSignal;
+    use crate::events::Event;
+    use crate::tracker::State;
+    use std::fs;
+
+    fn request(addr: SocketAddr, method: &str, path: &str) -> String {
+        request_with(addr, method, path, "")
+    }
+
+    fn request_with(addr: SocketAddr, method: &str, path: &str, headers: &str) -> String {
+        let headers = format!("Host: localhost:{}\r\n{}", addr.port(), headers);
+        request_raw(addr, method, path, &headers)
+    }
+
+    fn request_raw(addr: SocketAddr, method: &str, path: &str, headers: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\n{}\r\n", method, path, headers).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_server_reports_status_and_takes_commands() {
+        let dir = std::env::temp_dir();
+        let status_path = dir.join(format!("tomatina-http-{}.status.json", std::process::id()));
+        let history_path = dir.join(format!("tomatina-http-{}.jsonl", std::process::id()));
+        Status {
+            state: State::Working,
+            ends: Some(1500),
+            overdue_since: None,
+            task: None,
+            completed_today: 2,
+            daily_goal: None,
+            paused: false,
+            updated: 1000,
+        }
+        .write(&status_path)
+        .unwrap();
+        let mut signal = ButtonSignal::in_process().unwrap();
+        let config = HttpConfig {
+            listen: "127.0.0.1:0".to_string(),
+        };
+        let server = Server::start(
+            &config,
+            status_path.clone(),
+            history_path,
+            signal.sender(),
+            Broadcaster::default(),
+        )
+        .unwrap();
+
+        let response = request(server.addr(), "GET", "/status");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("\"state\":\"Working\""));
+        let response = request(server.addr(), "GET", "/history");
+        assert!(response.ends_with("\r\n\r\n[]"));
+        assert!(request(server.addr(), "GET", "/").contains("<html"));
+        assert!(request(server.addr(), "GET", "/next").starts_with("HTTP/1.1 405"));
+        assert!(request(server.addr(), "GET", "/nope").starts_with("HTTP/1.1 404"));
+
+        let foreign = "Origin: https://example.com\r\n";
+        assert!(request_with(server.addr(), "POST", "/next", foreign).starts_with("HTTP/1.1 403"));
+        let dashboard = format!("Origin: http://localhost:{}\r\n", server.addr().port());
+        assert!(
+            request_with(server.addr(), "POST", "/next", &dashboard).starts_with("HTTP/1.1 202")
+        );
+        // a page whose name has been rebound to our address
+        let rebound = format!("Host: evil.example:{}\r\n", server.addr().port());
+        assert!(request_raw(server.addr(), "GET", "/status", &rebound).starts_with("HTTP/1.1 403"));
+        assert!(request_raw(server.addr(), "POST", "/next", &rebound).starts_with("HTTP/1.1 403"));
+        assert!(request_raw(server.addr(), "POST", "/next", "").starts_with("HTTP/1.1 403"));
+        let listen_addr = format!("Host: {}\r\n", server.addr());
+        assert!(request_raw(server.addr(), "GET", "/", &listen_addr).starts_with("HTTP/1.1 200"));
+        assert!(request(server.addr(), "POST", "/pause").starts_with("HTTP/1.1 202"));
+        let timeout = Some(Duration::from_secs(5));
+        assert_eq!(
+            signal.wait(timeout).unwrap(),
+            Some(Command::Press(ButtonPress::Primary))
+        );
+        assert_eq!(signal.wait(timeout).unwrap(), Some(Command::Pause));
+        fs::remove_file(status_path).unwrap();
+    }
+
+    #[test]
+    fn test_server_streams_events() {
+        let signal = ButtonSignal::in_process().unwrap();
+        let events = Broadcaster::default();
+        let config = HttpConfig {
+            listen: "127.0.0.1:0".to_string(),
+        };
+        let path = std::env::temp_dir().join("tomatina-http-unused");
+        let server =
+            Server::start(&config, path.clone(), path, signal.sender(), events.clone()).unwrap();
+        let mut stream = TcpStream::connect(server.addr()).unwrap();
+        write!(
+            stream,
+            "GET /events HTTP/1.1\r\nHost: {}\r\n\r\n",
+            server.addr()
+        )
+        .unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut line = String::new();
+        while line != "\r\n" {
+            line.clear();
+            reader.read_line(&mut line).unwrap();
+        }
+        events.send(Event::PressReceived {
+            button: ButtonPress::Secondary,
+        });
+        let mut event = String::new();
+        for _ in 0..3 {
+            reader.read_line(&mut event).unwrap();
+        }
+        assert_eq!(
+            event,
+            "event: press_received\n\
+             data: {\"type\":\"press_received\",\"button\":\"secondary\"}\n\n"
+        );
+    }
+}
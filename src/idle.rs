@@ -0,0 +1,201 @@
+use crate::shell;
+
+use serde::Deserialize;
+use std::io;
+use std::process::Command;
+use std::time::Duration;
+#[cfg(test)]
+use std::{cell::Cell, rc::Rc};
+
+/// Reports how long the user has been away from the keyboard.
+pub trait IdleSource {
+    fn idle_time(&self) -> io::Result<Duration>;
+}
+
+/// The idle hint the desktop reports to systemd-logind for this session.
+pub struct Logind;
+
+impl IdleSource for Logind {
+    fn idle_time(&self) -> io::Result<Duration> {
+        let output = shell::output(Command::new("loginctl").args([
+            "show-session",
+            "auto",
+            "-p",
+            "IdleHint",
+            "-p",
+            "IdleSinceHintMonotonic",
+        ]))?;
+        parse_logind(&output, monotonic_now()?)
+    }
+}
+
+/// The X11 screensaver extension, as read by `xprintidle`.
+pub struct X11;
+
+impl IdleSource for X11 {
+    fn idle_time(&self) -> io::Result<Duration> {
+        parse_xprintidle(&shell::output(&mut Command::new("xprintidle"))?)
+    }
+}
+
+/// What happens to a work interval once the user has been away too long.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IdleAction {
+    /// Stop the clock until they come back.
+    Pause,
+    /// Record the interval as interrupted and wait for work to be started again.
+    Abandon,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceKind {
+    Logind,
+    X11,
+}
+
+/// The `[idle]` section of the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IdleConfig {
+    pub source: SourceKind,
+    pub threshold_mins: u64,
+    pub action: IdleAction,
+}
+
+impl Default for IdleConfig {
+    fn default() -> Self {
+        Self {
+            source: SourceKind::Logind,
+            threshold_mins: 5,
+            action: IdleAction::Pause,
+        }
+    }
+}
+
+pub struct IdleDetector {
+    pub source: Box<dyn IdleSource>,
+    pub threshold: Duration,
+    pub action: IdleAction,
+}
+
+impl From<IdleConfig> for IdleDetector {
+    fn from(config: IdleConfig) -> Self {
+        Self {
+            source: match config.source {
+                SourceKind::Logind => Box::new(Logind),
+                SourceKind::X11 => Box::new(X11),
+            },
+            threshold: Duration::from_secs(60 * config.threshold_mins),
+            action: config.action,
+        }
+    }
+}
+
+/// Microseconds on CLOCK_MONOTONIC, which logind uses for its timestamps.
+fn monotonic_now() -> io::Result<u64> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(time.tv_sec as u64 * 1_000_000 + time.tv_nsec as u64 / 1_000)
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Parses the `key=value` properties from `loginctl show-session`.
+fn parse_logind(output: &str, now_usec: u64) -> io::Result<Duration> {
+    let mut idle = None;
+    let mut since = None;
+    for line in output.lines() {
+        match line.trim().split_once('=') {
+            Some(("IdleHint", value)) => idle = Some(value == "yes"),
+            Some(("IdleSinceHintMonotonic", value)) => {
+                since = Some(value.parse::<u64>().map_err(|e| {
+                    invalid(format!("invalid IdleSinceHintMonotonic {:?}: {}", value, e))
+                })?)
+            }
+            _ => {}
+        }
+    }
+    match (idle, since) {
+        (Some(false), _) => Ok(Duration::from_secs(0)),
+        (Some(true), Some(since)) => Ok(Duration::from_micros(now_usec.saturating_sub(since))),
+        _ => Err(invalid(format!("unexpected loginctl output: {:?}", output))),
+    }
+}
+
+fn parse_xprintidle(output: &str) -> io::Result<Duration> {
+    output
+        .trim()
+        .parse()
+        .map(Duration::from_millis)
+        .map_err(|e| invalid(format!("unexpected xprintidle output {:?}: {}", output, e)))
+}
+
+/// An idle time set by hand. Clones share the same time.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct FakeIdle {
+    idle_time: Rc<Cell<Duration>>,
+}
+
+#[cfg(test)]
+impl FakeIdle {
+    pub fn set(&self, idle_time: Duration) {
+        self.idle_time.set(idle_time);
+    }
+}
+
+#[cfg(test)]
+impl IdleSource for FakeIdle {
+    fn idle_time(&self) -> io::Result<Duration> {
+        Ok(self.idle_time.get())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_logind_measures_from_the_idle_hint() {
+        assert_eq!(
+            parse_logind("IdleHint=yes\nIdleSinceHintMonotonic=1000000\n", 61_000_000).unwrap(),
+            Duration::from_secs(60)
+        );
+        assert_eq!(
+            parse_logind("IdleHint=no\nIdleSinceHintMonotonic=0\n", 61_000_000).unwrap(),
+            Duration::from_secs(0)
+        );
+        assert!(parse_logind("", 0).is_err());
+    }
+
+    #[test]
+    fn test_parse_xprintidle_reads_milliseconds() {
+        assert_eq!(
+            parse_xprintidle("1500\n").unwrap(),
+            Duration::from_millis(1500)
+        );
+        assert!(parse_xprintidle("error").is_err());
+    }
+
+    #[test]
+    fn test_config_defaults_to_pausing_after_five_minutes() {
+        let config: IdleConfig = toml::from_str("source = \"x11\"").unwrap();
+        assert_eq!(
+            config,
+            IdleConfig {
+                source: SourceKind::X11,
+                threshold_mins: 5,
+                action: IdleAction::Pause,
+            }
+        );
+    }
+}
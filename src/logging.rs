@@ -0,0 +1,200 @@
+use chrono::{DateTime, Local, SecondsFormat};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+
+/// How many rotated log files are kept alongside the current one.
+const KEEP_ROTATED: u32 = 5;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    /// One line per message for reading.
+    Text,
+    /// One JSON object per line for log collectors.
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("unknown log format: {}", s)),
+        }
+    }
+}
+
+/// Writes log messages at or above a level to stderr or a rotating file.
+pub struct Logger {
+    level: LevelFilter,
+    format: Format,
+    file: Option<Mutex<RotatingFile>>,
+}
+
+impl Logger {
+    /// Logs to stderr unless given a file and the size to rotate it at.
+    pub fn new(
+        level: LevelFilter,
+        format: Format,
+        file: Option<(PathBuf, u64)>,
+    ) -> io::Result<Self> {
+        let file = match file {
+            Some((path, max_bytes)) => Some(Mutex::new(RotatingFile::open(path, max_bytes)?)),
+            None => None,
+        };
+        Ok(Self {
+            level,
+            format,
+            file,
+        })
+    }
+
+    /// Makes this the destination for the `log` macros.
+    pub fn install(self) {
+        let level = self.level;
+        log::set_boxed_logger(Box::new(self)).expect("logger already installed");
+        log::set_max_level(level);
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let line = format_record(self.format, record, Local::now());
+        let written = match &self.file {
+            Some(file) => file.lock().unwrap().write_line(&line),
+            None => writeln!(io::stderr().lock(), "{}", line),
+        };
+        if written.is_err() && self.file.is_some() {
+            // there's nowhere better to say so, and the message shouldn't be lost
+            let _ = writeln!(io::stderr().lock(), "{}", line);
+        }
+    }
+
+    fn flush(&self) {
+        if let Some(file) = &self.file {
+            let _ = file.lock().unwrap().file.flush();
+        }
+    }
+}
+
+fn format_record(format: Format, record: &Record, now: DateTime<Local>) -> String {
+    let timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, false);
+    match format {
+        Format::Text => format!("{} {:<5} {}", timestamp, record.level(), record.args()),
+        Format::Json => serde_json::json!({
+            "timestamp": timestamp,
+            "level": record.level().as_str(),
+            "target": record.target(),
+            "message": record.args().to_string(),
+        })
+        .to_string(),
+    }
+}
+
+/// A log file that is moved aside to `<path>.1`, `<path>.2` and so on once it
+/// reaches `max_bytes`.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    size: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path,
+            max_bytes,
+            file,
+            size,
+        })
+    }
+
+    fn write_line(&mut self, line: &str) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        if self.size > 0 && self.size + len > self.max_bytes {
+            self.rotate()?;
+        }
+        writeln!(self.file, "{}", line)?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        for n in (1..KEEP_ROTATED).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                fs::rename(from, rotated(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated(&self.path, 1))?;
+        *self = Self::open(self.path.clone(), self.max_bytes)?;
+        Ok(())
+    }
+}
+
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use log::Level;
+
+    #[test]
+    fn test_records_are_formatted_as_text_or_json() {
+        let now = Local.timestamp_opt(1_000_000_000, 0).unwrap();
+        let timestamp = now.to_rfc3339_opts(SecondsFormat::Millis, false);
+        let args = format_args!("State changed to {:?}", "Working");
+        let record = Record::builder()
+            .level(Level::Info)
+            .target("tomatina::manager")
+            .args(args)
+            .build();
+        assert_eq!(
+            format_record(Format::Text, &record, now),
+            format!("{} INFO  State changed to \"Working\"", timestamp)
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&format_record(Format::Json, &record, now)).unwrap();
+        assert_eq!(json["timestamp"], timestamp);
+        assert_eq!(json["level"], "INFO");
+        assert_eq!(json["target"], "tomatina::manager");
+        assert_eq!(json["message"], "State changed to \"Working\"");
+    }
+
+    #[test]
+    fn test_file_is_rotated_once_full() {
+        let dir = std::env::temp_dir().join(format!("tomatina-logging-{}", std::process::id()));
+        let path = dir.join("tomatina.log");
+        let mut file = RotatingFile::open(path.clone(), 10).unwrap();
+        for line in &["first", "second", "third"] {
+            file.write_line(line).unwrap();
+        }
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "first\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,11 +1,50 @@
+mod blocker;
 mod button;
+mod clock;
+mod config;
+mod control;
+mod daemon;
+mod dnd;
+mod doctor;
+mod error;
+mod events;
+mod export;
+mod files;
+mod history;
+mod hooks;
+mod http;
+mod idle;
+mod logging;
 mod manager;
+mod mqtt;
+mod pidfile;
+mod progress;
+mod runtime;
+mod schedule;
+mod setup;
+mod shell;
+mod stats;
+mod status;
+mod sync;
+mod todo;
 mod tracker;
+mod webhooks;
 
-extern crate libusb;
+extern crate rusb;
 
-use std::time::Duration;
+use chrono::{Local, NaiveDate};
+use error::Error;
+use history::History;
+use log::{error, LevelFilter};
+use status::Status;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::process;
+use std::time::{Duration, SystemTime};
 use structopt::StructOpt;
+use tracker::SuspendPolicy;
 
 #[derive(StructOpt, Debug)]
 #[structopt(about = "Use a USBButton as a pomodoro timer")]
@@ -18,14 +57,227 @@ struct Opt {
 
     #[structopt(long, default_value = "20")]
     long_break: u64,
+
+    /// What to do with a running phase after the machine wakes from sleep: "complete" or "interrupt"
+    #[structopt(long, default_value = "complete")]
+    on_suspend: SuspendPolicy,
+
+    /// TOML file with further settings [default: $XDG_CONFIG_HOME/tomatina/config.toml]
+    #[structopt(long, parse(from_os_str))]
+    config: Option<PathBuf>,
+
+    /// File to log completed phases to [default: $XDG_DATA_HOME/tomatina/history.jsonl]
+    #[structopt(long, parse(from_os_str))]
+    history: Option<PathBuf>,
+
+    /// Change the button color the longer it is left waiting for a press
+    #[structopt(long)]
+    overtime_colors: bool,
+
+    /// Label for work intervals until another task is chosen
+    #[structopt(long)]
+    task: Option<String>,
+
+    /// todo.txt or Markdown checklist whose open tasks a long press cycles through
+    #[structopt(long, parse(from_os_str))]
+    todo: Option<PathBuf>,
+
+    /// Number of work intervals to aim for each day
+    #[structopt(long)]
+    daily_goal: Option<u32>,
+
+    /// Take the button from the kernel's HID driver while setting its colors, if it's refused otherwise
+    #[structopt(long)]
+    detach_kernel_driver: bool,
+
+    /// Ask an already running tomatina to exit and take over from it
+    #[structopt(long)]
+    takeover: bool,
+
+    /// Only log warnings and errors
+    #[structopt(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+
+    /// Also log button presses and the time remaining at each minute
+    #[structopt(short, long)]
+    verbose: bool,
+
+    /// "text" or "json"
+    #[structopt(long, default_value = "text")]
+    log_format: logging::Format,
+
+    /// File to log to instead of stderr
+    #[structopt(long, parse(from_os_str))]
+    log_file: Option<PathBuf>,
+
+    /// Size in MiB at which the log file is moved aside to <log-file>.1
+    #[structopt(long, default_value = "10")]
+    log_max_size: u64,
+
+    #[structopt(subcommand)]
+    command: Option<Subcommand>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Subcommand {
+    /// Label the current and following work intervals of a running tomatina; omit the label to clear it
+    Task { label: Option<String> },
+
+    /// Pause the current phase of a running tomatina, or resume it if it's paused
+    Pause,
+
+    /// Run under a service manager: exit cleanly on SIGTERM, reload the config on SIGHUP and
+    /// treat SIGUSR1 as a button press
+    Daemon {
+        /// Print a systemd user unit that runs tomatina with the other options given
+        #[structopt(long)]
+        print_unit: bool,
+    },
+
+    /// Set up the system so tomatina can use the button without root
+    Setup(Platform),
+
+    /// Check that the button is plugged in and tomatina is allowed to use it
+    Doctor,
+
+    /// Show the state of a running tomatina and today's progress
+    Status,
+
+    /// Show the time spent working on each task
+    Stats,
+
+    /// Write the history as CSV, JSON Lines or iCalendar events
+    Export {
+        /// "csv", "json" or "ics"
+        #[structopt(long, default_value = "csv")]
+        format: export::Format,
+
+        /// First day to include, as YYYY-MM-DD
+        #[structopt(long)]
+        from: Option<NaiveDate>,
+
+        /// Last day to include, as YYYY-MM-DD
+        #[structopt(long)]
+        to: Option<NaiveDate>,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+enum Platform {
+    /// Install a udev rule that gives the user at the console access to the button
+    Linux {
+        /// Where to write the rule
+        #[structopt(long, parse(from_os_str), default_value = setup::UDEV_RULE_PATH)]
+        rule: PathBuf,
+    },
 }
 
 fn main() {
-    let opt = Opt::from_args();
+    let mut opt = Opt::from_args();
+    let level = if opt.quiet {
+        LevelFilter::Warn
+    } else if opt.verbose {
+        LevelFilter::Debug
+    } else {
+        LevelFilter::Info
+    };
+    let max_log_bytes = opt.log_max_size * 1024 * 1024;
+    let log_file = opt.log_file.take().map(|path| (path, max_log_bytes));
+    match logging::Logger::new(level, opt.log_format, log_file) {
+        Ok(logger) => logger.install(),
+        Err(e) => {
+            // there's no logger to report this through yet
+            eprintln!("failed to open log file: {}", e);
+            process::exit(1);
+        }
+    }
+    if let Err(e) = run(opt) {
+        error!("{}", e);
+        process::exit(e.exit_code());
+    }
+}
+
+fn run(opt: Opt) -> error::Result<()> {
+    let history_path = opt.history.unwrap_or_else(History::default_path);
+    let mut daemon = false;
+    match opt.command {
+        None => {}
+        Some(Subcommand::Daemon { print_unit: true }) => {
+            let exe = env::current_exe()
+                .and_then(fs::canonicalize)
+                .map_err(Error::io("find the tomatina executable"))?;
+            let dir = env::current_dir().map_err(Error::io("find the current directory"))?;
+            let args: Vec<String> = env::args()
+                .skip(1)
+                .filter(|arg| arg != "--print-unit")
+                .collect();
+            let args = daemon::absolute_paths(&args, &dir);
+            print!("{}", daemon::unit(&exe.to_string_lossy(), &args));
+            return Ok(());
+        }
+        Some(Subcommand::Daemon { print_unit: false }) => daemon = true,
+        Some(Subcommand::Task { label }) => {
+            return control::send(&control::Command::SetTask(label))
+                .map_err(Error::io("set the task"));
+        }
+        Some(Subcommand::Pause) => {
+            return control::send(&control::Command::Pause).map_err(Error::io("pause"));
+        }
+        Some(Subcommand::Setup(Platform::Linux { rule })) => return setup::linux(&rule),
+        Some(Subcommand::Doctor) => return doctor::run(&rusb::Context::new()?),
+        Some(Subcommand::Status) => {
+            let status = Status::read(&Status::default_path())
+                .map_err(Error::io("read the status, is tomatina running?"))?;
+            println!("{}", status.describe(SystemTime::now()));
+            return Ok(());
+        }
+        Some(Subcommand::Stats) => {
+            let records = History::new(history_path)
+                .read()
+                .map_err(Error::io("read the history"))?;
+            stats::print(&records);
+            return Ok(());
+        }
+        Some(Subcommand::Export { format, from, to }) => {
+            let records = History::new(history_path)
+                .read()
+                .map_err(Error::io("read the history"))?;
+            let records = export::between(&records, from, to, &Local);
+            return export::write(&mut io::stdout(), format, &records, &Local)
+                .map_err(Error::io("write the export"));
+        }
+    }
+    let config_path = opt.config.unwrap_or_else(config::Config::default_path);
+    let file_config = config::Config::load(&config_path)?;
+    runtime::prepare()?;
+    let _pid_file = pidfile::PidFile::acquire(&pidfile::PidFile::default_path(), opt.takeover)?;
     let config = tracker::TrackerConfig {
         work_duration: Duration::from_secs(60 * opt.work),
         short_break_duration: Duration::from_secs(60 * opt.short_break),
         long_break_duration: Duration::from_secs(60 * opt.long_break),
+        suspend_policy: opt.on_suspend,
+    };
+    let mut manager_config = manager::ManagerConfig {
+        history_path,
+        status_path: Status::default_path(),
+        overtime_colors: opt.overtime_colors,
+        task: opt.task,
+        todo_path: opt.todo,
+        daily_goal: opt.daily_goal,
+        schedule: None,
+        idle: None,
+        hooks: Default::default(),
+        dnd: None,
+        blocker: None,
+        sync: file_config.sync.clone(),
+        http: file_config.http.clone(),
+        mqtt: file_config.mqtt.clone(),
+        webhooks: Vec::new(),
+        config_path: Some(config_path),
+        daemon,
+        exit: file_config.exit.clone(),
+        detach_kernel_driver: opt.detach_kernel_driver,
     };
-    manager::run(config);
+    manager_config.apply(file_config);
+    manager::run(config, manager_config, clock::SystemClock)
 }
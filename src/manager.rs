@@ -1,113 +1,744 @@
-use crate::button::{Button, Color};
+use crate::blocker::HostsBlocker;
+use crate::button::{Button, Color, ExitConfig};
+use crate::clock::Clock;
+use crate::config::Config;
+use crate::control::{ButtonPress, ButtonSignal, Command};
+use crate::daemon;
+use crate::dnd::DoNotDisturb;
+use crate::error::{Error, Result};
+use crate::events::{Broadcaster, Event};
+use crate::history::{unix_secs, History, Outcome};
+use crate::hooks::HooksConfig;
+use crate::http::{HttpConfig, Server};
+use crate::idle::{IdleAction, IdleDetector};
+use crate::mqtt::{Mqtt, MqttConfig};
+use crate::progress::DailyProgress;
+use crate::schedule::{OffHours, Schedule};
+use crate::status::Status;
+use crate::sync::{Session, SyncConfig, Update};
+use crate::todo::TodoFile;
 use crate::tracker::{State, Tracker, TrackerConfig};
+use crate::webhooks::{Transition, WebhookConfig, Webhooks};
 
-use libc::mkfifo;
-use std::collections::HashMap;
-use std::ffi::CString;
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime};
+use log::{debug, error, info, warn};
 use std::fs;
-use std::io::Read;
-use std::io::Result;
-use std::os::unix::fs::OpenOptionsExt;
-use std::path::Path;
+use std::path::PathBuf;
 use std::thread::sleep;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
 const RED: Color = Color(255, 0, 0);
 const GREEN: Color = Color(0, 255, 0);
 const BLUE: Color = Color(0, 0, 255);
 const PURPLE: Color = Color(255, 0, 255);
+const ORANGE: Color = Color(255, 128, 0);
+const YELLOW: Color = Color(255, 255, 0);
+const HOT_PINK: Color = Color(255, 0, 128);
+const OFF: Color = Color(0, 0, 0);
 
-pub fn run(config: TrackerConfig) {
-    let colors: HashMap<State, Color> = [
-        (State::PendingWork, RED),
-        (State::Working, GREEN),
-        (State::PendingShortBreak, RED),
-        (State::ShortBreak, BLUE),
-        (State::PendingLongBreak, RED),
-        (State::LongBreak, PURPLE),
-    ]
-    .iter()
-    .cloned()
-    .collect();
-
-    let context = libusb::Context::new().unwrap();
-    let button = Button::connect(&context);
-    let mut tracker = Tracker::new(config);
-    let mut signal = ButtonSignal::create().expect("failed to create pipe to button");
-    button.configure(colors.get(&tracker.state).unwrap());
-    let loop_interval = Duration::from_millis(50);
-    println!("Initial state: {:?}", tracker.state);
+/// Colors a pending state escalates through the longer it is left waiting,
+/// from a warning to an alarm no other state uses.
+const OVERTIME_COLORS: [(Duration, Color); 3] = [
+    (Duration::from_secs(5 * 60), YELLOW),
+    (Duration::from_secs(10 * 60), ORANGE),
+    (Duration::from_secs(15 * 60), HOT_PINK),
+];
+
+/// Played when the daily goal is reached.
+const CELEBRATION: [Color; 6] = [RED, ORANGE, YELLOW, GREEN, BLUE, PURPLE];
+const CELEBRATION_FRAME: Duration = Duration::from_millis(250);
+
+const LOG_INTERVAL: Duration = Duration::from_secs(60);
+const LOG_TOLERANCE: Duration = Duration::from_millis(50);
+/// How long before a phase runs out to send a warning event.
+const WARNING_BEFORE: Duration = Duration::from_secs(60);
+
+/// How often to check whether the user has walked away while working.
+const IDLE_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+pub trait Light {
+    fn set_color(&self, color: &Color) -> Result<()>;
+
+    fn celebrate(&self) -> Result<()> {
+        for _ in 0..2 {
+            for color in &CELEBRATION {
+                self.set_color(color)?;
+                sleep(CELEBRATION_FRAME);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Light for Button {
+    fn set_color(&self, color: &Color) -> Result<()> {
+        Button::set_color(self, color)
+    }
+}
+
+pub struct ManagerConfig {
+    pub history_path: PathBuf,
+    pub status_path: PathBuf,
+    /// Escalate the button color the longer a pending state is left waiting.
+    pub overtime_colors: bool,
+    /// Label given to work intervals until another is chosen.
+    pub task: Option<String>,
+    /// Task list that secondary presses cycle through.
+    pub todo_path: Option<PathBuf>,
+    /// Work intervals to complete each day.
+    pub daily_goal: Option<u32>,
+    /// Hours outside of which the button is switched off.
+    pub schedule: Option<Schedule>,
+    /// Pauses or abandons work while the user is away.
+    pub idle: Option<IdleDetector>,
+    /// Commands to run on entering each state.
+    pub hooks: HooksConfig,
+    /// Suppresses notifications while working.
+    pub dnd: Option<Box<dyn DoNotDisturb>>,
+    /// Blackholes distracting sites while working.
+    pub blocker: Option<HostsBlocker>,
+    /// Shares phases with other instances on the network.
+    pub sync: Option<SyncConfig>,
+    /// Serves a dashboard and JSON API.
+    pub http: Option<HttpConfig>,
+    /// Publishes to and takes commands from an MQTT broker.
+    pub mqtt: Option<MqttConfig>,
+    /// URLs to post state changes to.
+    pub webhooks: Vec<WebhookConfig>,
+    /// Where to reread the settings above from when asked to reload.
+    pub config_path: Option<PathBuf>,
+    /// Stop, reload or move on when signalled.
+    pub daemon: bool,
+    /// What to leave the button as on exit.
+    pub exit: ExitConfig,
+    /// Detach a kernel driver bound to the button while writing to it.
+    pub detach_kernel_driver: bool,
+}
+
+impl ManagerConfig {
+    /// Takes on the settings from the config file that can change while
+    /// running.
+    pub fn apply(&mut self, file_config: Config) {
+        self.schedule = file_config.schedule;
+        self.idle = file_config.idle.map(IdleDetector::from);
+        self.hooks = file_config.hooks;
+        self.dnd = file_config.dnd.map(|dnd| dnd.backend());
+        self.blocker = file_config.block.map(HostsBlocker::new);
+        self.webhooks = file_config.webhooks;
+    }
+}
+
+pub fn run<C: Clock>(tracker_config: TrackerConfig, config: ManagerConfig, clock: C) -> Result<()> {
+    let context = rusb::Context::new()?;
+    let mut button = Button::connect(&context, config.detach_kernel_driver)?;
+    button.set_exit(config.exit.clone());
+    let mut tracker = Tracker::new(tracker_config, clock);
+    tracker.set_task(config.task.clone());
+    let mut signal = ButtonSignal::create().map_err(Error::io("create the command pipe"))?;
+    button.configure(&state_color(tracker.state))?;
+    let sync = config.sync.clone();
+    let mut manager = Manager::new(tracker, button, config);
+    daemon::forward_signals(signal.sender(), manager.config.daemon)
+        .map_err(Error::io("handle signals"))?;
+    if let Some(http) = &manager.config.http {
+        Server::start(
+            http,
+            manager.config.status_path.clone(),
+            manager.config.history_path.clone(),
+            signal.sender(),
+            manager.events.clone(),
+        )
+        .map_err(Error::io("start the HTTP server"))?;
+    }
+    if let Some(mqtt) = manager.config.mqtt.clone() {
+        Mqtt::start(
+            mqtt,
+            manager.tracker.state,
+            signal.sender(),
+            &manager.events,
+        )?;
+    }
+    if let Some(sync) = sync {
+        let session = Session::start(&sync, signal.sender())
+            .map_err(Error::io("start the shared session"))?;
+        manager.sync = Some(session);
+    }
+    info!("Initial state: {:?}", manager.tracker.state);
+    let result = serve(&mut manager, &mut signal);
+    info!("Shutting down");
+    daemon::notify("STOPPING=1");
+    // put things back however we came to stop, reporting the first error
+    let shutdown = manager.shutdown();
+    result.and(shutdown)
+}
+
+/// Acts on commands and timeouts until told to shut down.
+fn serve<C: Clock, L: Light>(manager: &mut Manager<C, L>, signal: &mut ButtonSignal) -> Result<()> {
+    manager.handle(None)?;
+    manager.publish();
+    daemon::notify("READY=1");
     loop {
-        let init_state = tracker.state;
-        if let Some(ButtonPress::Primary) = signal.poll() {
-            println!("Detected button press");
-            tracker.next();
-        }
-        tracker.tick(Instant::now());
-        if tracker.state != init_state {
-            println!("State changed from {:?} to {:?}", init_state, tracker.state);
-            button.set_color(colors.get(&tracker.state).unwrap());
-        }
-        if let Some(t) = tracker.time_remaining(Instant::now()) {
-            if let Some(t) = loggable_time_remaining(t, loop_interval) {
-                println!(
+        match signal
+            .wait(manager.timeout())
+            .map_err(Error::io("read commands"))?
+        {
+            Some(Command::Shutdown) => return Ok(()),
+            command => manager.handle(command)?,
+        }
+    }
+}
+
+fn state_color(state: State) -> Color {
+    match state {
+        State::PendingWork | State::PendingShortBreak | State::PendingLongBreak => RED,
+        State::Working => GREEN,
+        State::ShortBreak => BLUE,
+        State::LongBreak => PURPLE,
+    }
+}
+
+struct Manager<C: Clock, L: Light> {
+    tracker: Tracker<C>,
+    light: L,
+    color: Color,
+    history: History,
+    progress: DailyProgress,
+    todo: Option<TodoFile>,
+    off_hours: bool,
+    /// The last day we were within working hours.
+    work_date: Option<NaiveDate>,
+    /// Whether distractions are currently held back.
+    focused: bool,
+    /// Whether the current pause is down to the user being away, rather than
+    /// asked for.
+    idle_paused: bool,
+    sync: Option<Session>,
+    events: Broadcaster,
+    /// The whole minutes remaining in the phase last sent as a tick, and
+    /// whether its warning has gone out.
+    ticked: Option<u64>,
+    warned: bool,
+    webhooks: Webhooks,
+    config: ManagerConfig,
+}
+
+impl<C: Clock, L: Light> Manager<C, L> {
+    fn new(tracker: Tracker<C>, light: L, config: ManagerConfig) -> Self {
+        let color = state_color(tracker.state);
+        let history = History::new(config.history_path.clone());
+        let records = history.read().unwrap_or_else(|e| {
+            error!("failed to read history: {}", e);
+            Vec::new()
+        });
+        let progress = DailyProgress::new(&records, today(&tracker));
+        if let Some(blocker) = &config.blocker {
+            // clear up after a run that didn't get to unblock
+            if let Err(e) = blocker.unblock() {
+                error!("failed to restore hosts file: {}", e);
+            }
+        }
+        Self {
+            tracker,
+            light,
+            color,
+            history,
+            progress,
+            todo: config.todo_path.clone().map(TodoFile::new),
+            off_hours: false,
+            work_date: None,
+            focused: false,
+            idle_paused: false,
+            sync: None,
+            events: Broadcaster::default(),
+            ticked: None,
+            warned: false,
+            webhooks: Webhooks::start(&config.webhooks),
+            config,
+        }
+    }
+
+    /// How long the loop may sleep before it next has something to do.
+    fn timeout(&self) -> Option<Duration> {
+        let next_log = if self.tracker.is_paused() {
+            None
+        } else {
+            self.tracker.time_remaining().map(time_until_next_log)
+        };
+        let next_escalation = if self.config.overtime_colors {
+            self.tracker.overtime().and_then(|overtime| {
+                OVERTIME_COLORS
+                    .iter()
+                    .map(|(after, _)| *after)
+                    .find(|after| *after > overtime)
+                    .map(|after| after - overtime)
+            })
+        } else {
+            None
+        };
+        let next_schedule_change = self.config.schedule.as_ref().and_then(|schedule| {
+            let now = self.local_now();
+            let change = schedule.next_change(now)?;
+            (change - now).to_std().ok()
+        });
+        let next_idle_check = match self.config.idle {
+            Some(_) if self.tracker.state == State::Working => Some(IDLE_POLL_INTERVAL),
+            _ => None,
+        };
+        earliest(
+            earliest(next_log, next_escalation),
+            earliest(next_schedule_change, next_idle_check),
+        )
+    }
+
+    fn color(&self) -> Color {
+        if self.off_hours {
+            return OFF;
+        }
+        let base = state_color(self.tracker.state);
+        let overtime = match self.tracker.overtime() {
+            Some(overtime) if self.config.overtime_colors => overtime,
+            _ => return base,
+        };
+        OVERTIME_COLORS
+            .iter()
+            .rev()
+            .find(|(after, _)| overtime >= *after)
+            .map_or(base, |(_, color)| color.clone())
+    }
+
+    fn handle(&mut self, command: Option<Command>) -> Result<()> {
+        let init_state = self.tracker.state;
+        let init_paused = self.tracker.is_paused();
+        let init_elapsed = match self.tracker.phase_duration() {
+            Some(duration) => self.tracker.elapsed().min(duration),
+            None => self.tracker.elapsed(),
+        };
+        let off_hours = self.check_schedule();
+        self.check_idle();
+        if let Some(Command::Press(button)) = &command {
+            self.events.send(Event::PressReceived { button: *button });
+        }
+        match command {
+            Some(Command::Press(_)) if off_hours => {
+                info!("Ignoring button press outside working hours");
+            }
+            Some(Command::Press(ButtonPress::Primary)) => {
+                debug!("Detected button press");
+                match &self.sync {
+                    Some(Session::Follower(follower)) if follower.request_next() => {
+                        info!("Asked the leader to move on");
+                    }
+                    _ => self.tracker.next(),
+                }
+            }
+            Some(Command::Press(ButtonPress::Secondary)) => {
+                self.cycle_task();
+            }
+            Some(Command::SetTask(task)) => {
+                self.set_task(task);
+            }
+            Some(Command::Pause) if self.tracker.is_paused() => {
+                info!("Resuming {:?}", self.tracker.state);
+                self.tracker.resume();
+                self.idle_paused = false;
+            }
+            Some(Command::Pause) => {
+                self.tracker.pause(Duration::from_secs(0));
+                if self.tracker.is_paused() {
+                    info!("Paused {:?}", self.tracker.state);
+                }
+            }
+            Some(Command::Follow(update)) => {
+                self.tracker.follow(
+                    update.state,
+                    Duration::from_millis(update.elapsed_ms),
+                    update.overdue,
+                    update.intervals,
+                    update.paused,
+                );
+            }
+            Some(Command::Reload) => {
+                self.reload();
+            }
+            Some(Command::Shutdown) | None => {}
+        }
+        self.tracker.tick();
+        if self.tracker.state != init_state {
+            info!(
+                "State changed from {:?} to {:?}",
+                init_state, self.tracker.state
+            );
+            self.config
+                .hooks
+                .run(init_state, self.tracker.state, self.tracker.intervals());
+            self.publish();
+            self.webhooks.notify(&Transition {
+                state: self.tracker.state,
+                previous_state: init_state,
+                intervals: self.tracker.intervals(),
+                duration_secs: self.tracker.phase_duration().map(|d| d.as_secs()),
+                previous_duration_secs: init_elapsed.as_secs(),
+                task: self.tracker.task().map(String::from),
+                at: unix_secs(self.tracker.clock().system_time()),
+            });
+            self.events.send(Event::StateChanged {
+                from: init_state,
+                to: self.tracker.state,
+                intervals: self.tracker.intervals(),
+            });
+            self.ticked = None;
+            self.warned = false;
+        } else if self.tracker.is_paused() != init_paused {
+            // followers would carry on counting down otherwise
+            self.publish();
+        }
+        let color = self.color();
+        if color != self.color {
+            self.light.set_color(&color)?;
+            self.color = color;
+        }
+        self.update_focus();
+        self.save_records()?;
+        if let Err(e) = self.status().write(&self.config.status_path) {
+            error!("failed to write status: {}", e);
+        }
+        if let Some(t) = self
+            .tracker
+            .time_remaining()
+            .filter(|_| !self.tracker.is_paused())
+        {
+            if let Some(t) = loggable_time_remaining(t, LOG_TOLERANCE) {
+                debug!(
                     "Time remaining in state {:?}: {} mins",
-                    tracker.state,
+                    self.tracker.state,
                     t.as_secs() / 60,
                 );
             }
+            self.send_countdown(t);
         }
-        sleep(loop_interval);
+        Ok(())
     }
-}
 
-enum ButtonPress {
-    Primary,
-    Secondary,
+    /// Sends a tick whenever the whole minutes remaining change, and the
+    /// warning once in each phase. Going by what's changed since, rather than
+    /// when we woke, means a late wakeup can't lose them.
+    fn send_countdown(&mut self, time_remaining: Duration) {
+        let state = self.tracker.state;
+        let minutes = whole_minutes(time_remaining);
+        if self.ticked != Some(minutes) {
+            self.ticked = Some(minutes);
+            self.events.send(Event::Tick {
+                state,
+                remaining_secs: minutes * LOG_INTERVAL.as_secs(),
+            });
+        }
+        if time_remaining <= WARNING_BEFORE && !self.warned {
+            self.warned = true;
+            self.events.send(Event::Warning {
+                state,
+                remaining_secs: time_remaining.as_secs()
+                    + u64::from(time_remaining.subsec_nanos() > 0),
+            });
+        }
+    }
 }
 
-struct ButtonSignal {
-    file: fs::File,
-}
+impl<C: Clock, L: Light> Manager<C, L> {
+    /// Leaves things as they'd be without us running: the phase so far kept
+    /// in the history and distractions let back through. The button sees to
+    /// itself once it's dropped.
+    fn shutdown(&mut self) -> Result<()> {
+        self.tracker.tick();
+        self.tracker.stop();
+        self.save_records()?;
+        if self.focused {
+            self.set_focus(false);
+        }
+        if let Err(e) = fs::remove_file(&self.config.status_path) {
+            warn!("failed to remove status: {}", e);
+        }
+        Ok(())
+    }
+
+    /// Rereads the config file, taking on whatever can change without a
+    /// restart.
+    fn reload(&mut self) {
+        let path = match &self.config.config_path {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        daemon::notify("RELOADING=1");
+        match Config::load(&path) {
+            Ok(file_config) => {
+                if (
+                    &file_config.sync,
+                    &file_config.http,
+                    &file_config.mqtt,
+                    &file_config.exit,
+                ) != (
+                    &self.config.sync,
+                    &self.config.http,
+                    &self.config.mqtt,
+                    &self.config.exit,
+                ) {
+                    warn!("changes to [sync], [http], [mqtt] and [exit] need a restart");
+                }
+                // let distractions back under the old settings before taking
+                // on the new ones
+                if self.focused {
+                    self.set_focus(false);
+                }
+                self.config.apply(file_config);
+                self.webhooks = Webhooks::start(&self.config.webhooks);
+                info!("Reloaded {}", path.display());
+            }
+            Err(e) => error!("failed to reload config: {}", e),
+        }
+        daemon::notify("READY=1");
+    }
+
+    /// Files away the phases that have ended since the last call.
+    fn save_records(&mut self) -> Result<()> {
+        for record in self.tracker.take_records() {
+            if let Err(e) = self.history.append(&record) {
+                error!("failed to write history: {}", e);
+            }
+            if let (State::Working, Outcome::Completed, Some(task), Some(todo)) =
+                (record.state, record.outcome, &record.task, &self.todo)
+            {
+                match todo.add_pomodoro(task) {
+                    Ok(true) => {}
+                    Ok(false) => warn!("task {:?} is not in the todo file", task),
+                    Err(e) => error!("failed to update todo file: {}", e),
+                }
+            }
+            if self.progress.add(&record) {
+                self.check_goal()?;
+            }
+        }
+        Ok(())
+    }
+
+    fn set_task(&mut self, task: Option<String>) {
+        match &task {
+            Some(task) => info!("Task set to {:?}", task),
+            None => info!("Task cleared"),
+        }
+        self.tracker.set_task(task);
+    }
 
-impl ButtonSignal {
-    fn create() -> Result<Self> {
-        let path = Path::new("/tmp/tomatina.fifo");
-        if path.exists() {
-            fs::remove_file(path)?;
+    /// Moves in or out of working hours, returning whether we're outside them.
+    fn check_schedule(&mut self) -> bool {
+        let schedule = match &self.config.schedule {
+            Some(schedule) => schedule,
+            None => return false,
+        };
+        let now = self.local_now();
+        let off_hours = !schedule.is_working(now);
+        if off_hours && !self.off_hours {
+            info!("Outside working hours");
+            if schedule.outside == OffHours::End {
+                // let a phase that has already run out finish normally
+                self.tracker.tick();
+                self.tracker.stop();
+            }
+        } else if !off_hours && self.off_hours {
+            info!("Working hours started");
+        }
+        if !off_hours {
+            if self.work_date.is_some_and(|date| date != now.date()) {
+                info!("Starting a new day's set");
+                self.tracker.reset_intervals();
+            }
+            self.work_date = Some(now.date());
+        }
+        self.off_hours = off_hours;
+        off_hours
+    }
+
+    /// Pauses or abandons work once the user has been away for too long, and
+    /// resumes it when they come back.
+    fn check_idle(&mut self) {
+        if !self.tracker.is_paused() {
+            self.idle_paused = false;
+        }
+        let idle = match &self.config.idle {
+            Some(idle) if self.tracker.state == State::Working => idle,
+            _ => return,
+        };
+        let idle_time = match idle.source.idle_time() {
+            Ok(idle_time) => idle_time,
+            Err(e) => {
+                warn!("failed to check idle time: {}", e);
+                return;
+            }
+        };
+        let away = idle_time >= idle.threshold;
+        if away && !self.tracker.is_paused() {
+            info!("Away for {} mins", idle_time.as_secs() / 60);
+            match idle.action {
+                IdleAction::Pause => {
+                    info!("Pausing work");
+                    self.tracker.pause(idle_time);
+                    self.idle_paused = true;
+                }
+                IdleAction::Abandon => {
+                    // work that ran out before they left still counts
+                    self.tracker.tick();
+                    if self.tracker.state == State::Working {
+                        info!("Abandoning work");
+                        self.tracker.stop();
+                    }
+                }
+            }
+        } else if !away && self.idle_paused && self.tracker.is_paused() {
+            info!("Resuming work");
+            self.tracker.resume();
+            self.idle_paused = false;
+        }
+    }
+
+    /// Holds back notifications and distracting sites while working, and lets
+    /// them through again otherwise.
+    fn update_focus(&mut self) {
+        let working = self.tracker.state == State::Working;
+        if working != self.focused {
+            self.set_focus(working);
         }
-        let path_str = CString::new(path.to_str().unwrap())?;
-        let result = unsafe { mkfifo(path_str.as_ptr(), 0o600) };
-        assert_eq!(result, 0);
-        let file = fs::OpenOptions::new()
-            .read(true)
-            .custom_flags(libc::O_NONBLOCK)
-            .open(path)?;
-        Ok(Self { file })
     }
 
-    fn poll(&mut self) -> Option<ButtonPress> {
-        let mut buf: [u8; 1] = [0; 1];
-        let read = self.file.read(&mut buf).expect("failed to read from pipe");
-        if read == 0 {
-            return None;
+    fn set_focus(&mut self, working: bool) {
+        // don't retry on every wakeup if something is broken
+        self.focused = working;
+        if let Some(dnd) = &mut self.config.dnd {
+            let result = if working {
+                debug!("Enabling do not disturb");
+                dnd.enable()
+            } else {
+                debug!("Restoring notifications");
+                dnd.restore()
+            };
+            if let Err(e) = result {
+                error!("failed to update do not disturb: {}", e);
+            }
         }
-        match buf[0] as char {
-            '1' => Some(ButtonPress::Primary),
-            '2' => Some(ButtonPress::Secondary),
-            c => {
-                eprintln!("unexpected button code: {}", c);
-                None
+        if let Some(blocker) = &self.config.blocker {
+            let result = if working {
+                debug!("Blocking distracting sites");
+                blocker.block()
+            } else {
+                debug!("Unblocking distracting sites");
+                blocker.unblock()
+            };
+            if let Err(e) = result {
+                error!("failed to update hosts file: {}", e);
             }
         }
     }
+
+    /// Brings any followers up to date with where we are.
+    fn publish(&self) {
+        if let Some(Session::Leader(leader)) = &self.sync {
+            leader.publish(Update {
+                state: self.tracker.state,
+                elapsed_ms: self.tracker.elapsed().as_millis() as u64,
+                overdue: self.tracker.overtime().is_some(),
+                intervals: self.tracker.intervals(),
+                paused: self.tracker.is_paused(),
+            });
+        }
+    }
+
+    fn local_now(&self) -> NaiveDateTime {
+        DateTime::<Local>::from(self.tracker.clock().system_time()).naive_local()
+    }
+
+    fn check_goal(&self) -> Result<()> {
+        let goal = match self.config.daily_goal {
+            Some(goal) => goal,
+            None => return Ok(()),
+        };
+        let completed = self.progress.completed_on(today(&self.tracker));
+        info!("Completed {}/{} intervals today", completed, goal);
+        if completed == goal {
+            info!("Daily goal reached");
+            self.light.celebrate()?;
+            self.light.set_color(&self.color)?;
+        }
+        Ok(())
+    }
+
+    fn status(&self) -> Status {
+        let now = self.tracker.clock().system_time();
+        Status {
+            state: self.tracker.state,
+            ends: self.tracker.time_remaining().map(|t| unix_secs(now + t)),
+            overdue_since: self.tracker.overtime().map(|t| unix_secs(now - t)),
+            task: self.tracker.task().map(String::from),
+            completed_today: self.progress.completed_on(today(&self.tracker)),
+            daily_goal: self.config.daily_goal,
+            paused: self.tracker.is_paused(),
+            updated: unix_secs(now),
+        }
+    }
+
+    /// Moves on to the open task in the todo file after the current one.
+    fn cycle_task(&mut self) {
+        let todo = match &self.todo {
+            Some(todo) => todo,
+            None => return,
+        };
+        let tasks = match todo.open_tasks() {
+            Ok(tasks) => tasks,
+            Err(e) => {
+                error!("failed to read todo file: {}", e);
+                return;
+            }
+        };
+        let current = self
+            .tracker
+            .task()
+            .and_then(|task| tasks.iter().position(|t| t == task));
+        let next = match current {
+            Some(i) => tasks.get(i + 1).or_else(|| tasks.first()),
+            None => tasks.first(),
+        };
+        self.set_task(next.cloned());
+    }
+}
+
+fn today<C: Clock>(tracker: &Tracker<C>) -> NaiveDate {
+    DateTime::<Local>::from(tracker.clock().system_time()).date_naive()
+}
+
+fn earliest(a: Option<Duration>, b: Option<Duration>) -> Option<Duration> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
 }
 
-fn loggable_time_remaining(time_remaining: Duration, loop_interval: Duration) -> Option<Duration> {
-    let half_interval = loop_interval / 2;
-    if (time_remaining + half_interval).as_millis() % 60_000 <= loop_interval.as_millis() {
+/// How long to sleep so that we wake at the next whole minute of `time_remaining`,
+/// which is also the moment the current state expires when under a minute is left.
+fn time_until_next_log(time_remaining: Duration) -> Duration {
+    let until_next = (time_remaining.as_nanos() % LOG_INTERVAL.as_nanos()) as u64;
+    if until_next == 0 {
+        LOG_INTERVAL
+    } else {
+        Duration::from_nanos(until_next)
+    }
+}
+
+/// `time_remaining` in minutes, counting a part minute as a whole one.
+fn whole_minutes(time_remaining: Duration) -> u64 {
+    time_remaining.as_nanos().div_ceil(LOG_INTERVAL.as_nanos()) as u64
+}
+
+fn loggable_time_remaining(time_remaining: Duration, tolerance: Duration) -> Option<Duration> {
+    let half_tolerance = tolerance / 2;
+    if (time_remaining + half_tolerance).as_millis() % 60_000 <= tolerance.as_millis() {
         Some(Duration::from_secs(
-            (time_remaining + half_interval).as_secs(),
+            (time_remaining + half_tolerance).as_secs(),
         ))
     } else {
         None
@@ -118,6 +749,650 @@ fn loggable_time_remaining(time_remaining: Duration, loop_interval: Duration) ->
 #[allow(non_snake_case)]
 mod tests {
     use super::*;
+    use crate::blocker::BlockConfig;
+    use crate::button::PRESSED_COLOR;
+    use crate::clock::MockClock;
+    use crate::dnd::FakeDnd;
+    use crate::idle::FakeIdle;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const PRIMARY: Option<Command> = Some(Command::Press(ButtonPress::Primary));
+
+    /// Stands in for the whole celebration sequence in `MockLight::colors`.
+    const CELEBRATED: Color = Color(1, 2, 3);
+
+    #[derive(Clone, Default)]
+    struct MockLight {
+        colors: Rc<RefCell<Vec<Color>>>,
+    }
+
+    impl Light for MockLight {
+        fn set_color(&self, color: &Color) -> Result<()> {
+            self.colors.borrow_mut().push(color.clone());
+            Ok(())
+        }
+
+        fn celebrate(&self) -> Result<()> {
+            self.set_color(&CELEBRATED)
+        }
+    }
+
+    fn history_path() -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "tomatina-manager-{}-{:?}.jsonl",
+            std::process::id(),
+            std::thread::current().id()
+        ))
+    }
+
+    fn status_path() -> std::path::PathBuf {
+        history_path().with_extension("status.json")
+    }
+
+    fn cleanup() {
+        let _ = std::fs::remove_file(history_path());
+        let _ = std::fs::remove_file(status_path());
+    }
+
+    fn config() -> ManagerConfig {
+        ManagerConfig {
+            history_path: history_path(),
+            status_path: status_path(),
+            overtime_colors: false,
+            task: None,
+            todo_path: None,
+            daily_goal: None,
+            schedule: None,
+            idle: None,
+            hooks: Default::default(),
+            dnd: None,
+            blocker: None,
+            sync: None,
+            http: None,
+            mqtt: None,
+            webhooks: Vec::new(),
+            config_path: None,
+            daemon: false,
+            exit: Default::default(),
+            detach_kernel_driver: false,
+        }
+    }
+
+    fn create_manager_with(
+        config: ManagerConfig,
+    ) -> (Manager<MockClock, MockLight>, MockClock, MockLight) {
+        let clock = MockClock::new();
+        let light = MockLight::default();
+        let tracker = Tracker::new(Default::default(), clock.clone());
+        let manager = Manager::new(tracker, light.clone(), config);
+        (manager, clock, light)
+    }
+
+    fn create_manager() -> (Manager<MockClock, MockLight>, MockClock, MockLight) {
+        cleanup();
+        create_manager_with(config())
+    }
+
+    #[test]
+    fn test_manager_sleeps_indefinitely_while_pending() {
+        let (manager, _, _) = create_manager();
+        assert_eq!(manager.timeout(), None);
+    }
+
+    #[test]
+    fn test_manager_turns_light_green_on_primary_press() {
+        let (mut manager, _, light) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(manager.tracker.state, State::Working);
+        assert_eq!(*light.colors.borrow(), vec![GREEN]);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_ignores_secondary_press() {
+        let (mut manager, _, light) = create_manager();
+        manager
+            .handle(Some(Command::Press(ButtonPress::Secondary)))
+            .unwrap();
+        assert_eq!(manager.tracker.state, State::PendingWork);
+        assert!(light.colors.borrow().is_empty());
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_wakes_at_each_minute_and_at_the_end_of_work() {
+        let (mut manager, clock, light) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(19 * 60 + 30));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(30)));
+        clock.advance(Duration::from_secs(30));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.tracker.state, State::PendingShortBreak);
+        assert_eq!(manager.timeout(), None);
+        assert_eq!(*light.colors.borrow(), vec![GREEN, RED]);
+        let history = std::fs::read_to_string(history_path()).unwrap();
+        assert_eq!(history.lines().count(), 1);
+        assert!(history.contains("\"Working\""));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_labels_work_with_the_chosen_task() {
+        let (mut manager, clock, _) = create_manager();
+        manager
+            .handle(Some(Command::SetTask(Some("write report".to_string()))))
+            .unwrap();
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        let history = std::fs::read_to_string(history_path()).unwrap();
+        assert!(history.contains("\"task\":\"write report\""));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_cycles_through_todo_tasks_and_counts_pomodoros() {
+        let todo_path =
+            std::env::temp_dir().join(format!("tomatina-manager-{}-todo.txt", std::process::id()));
+        std::fs::write(&todo_path, "write report\nx file taxes\ncall mum\n").unwrap();
+        let (mut manager, clock, _) = create_manager_with(ManagerConfig {
+            todo_path: Some(todo_path.clone()),
+            ..config()
+        });
+        let secondary = || Some(Command::Press(ButtonPress::Secondary));
+        manager.handle(secondary()).unwrap();
+        assert_eq!(manager.tracker.task(), Some("write report"));
+        manager.handle(secondary()).unwrap();
+        assert_eq!(manager.tracker.task(), Some("call mum"));
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        manager.handle(secondary()).unwrap();
+        assert_eq!(manager.tracker.task(), Some("write report"));
+        assert_eq!(
+            std::fs::read_to_string(&todo_path).unwrap(),
+            "write report\nx file taxes\ncall mum pomo:1\n"
+        );
+        std::fs::remove_file(todo_path).unwrap();
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_celebrates_reaching_the_daily_goal() {
+        let (mut manager, clock, light) = create_manager_with(ManagerConfig {
+            daily_goal: Some(2),
+            ..config()
+        });
+        for _ in 0..3 {
+            manager.handle(PRIMARY).unwrap(); // -> working
+            clock.advance(Duration::from_secs(20 * 60));
+            manager.handle(None).unwrap(); // -> pending short break
+            manager.handle(PRIMARY).unwrap(); // -> short break
+            clock.advance(Duration::from_secs(5 * 60));
+            manager.handle(None).unwrap(); // -> pending work
+        }
+        assert_eq!(
+            *light.colors.borrow(),
+            vec![
+                GREEN, RED, BLUE, RED, // 1
+                GREEN, RED, CELEBRATED, RED, BLUE, RED, // 2
+                GREEN, RED, BLUE, RED, // 3
+            ]
+        );
+        let status = Status::read(&status_path()).unwrap();
+        assert_eq!(status.completed_today, 3);
+        assert_eq!(status.daily_goal, Some(2));
+        cleanup();
+    }
+
+    fn idle_config(action: IdleAction) -> (ManagerConfig, FakeIdle) {
+        let idle = FakeIdle::default();
+        let config = ManagerConfig {
+            idle: Some(IdleDetector {
+                source: Box::new(idle.clone()),
+                threshold: Duration::from_secs(5 * 60),
+                action,
+            }),
+            ..config()
+        };
+        (config, idle)
+    }
+
+    #[test]
+    fn test_manager_pauses_work_while_away() {
+        let (config, idle) = idle_config(IdleAction::Pause);
+        let (mut manager, clock, _) = create_manager_with(config);
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(manager.timeout(), Some(IDLE_POLL_INTERVAL));
+        clock.advance(Duration::from_secs(10 * 60));
+        idle.set(Duration::from_secs(5 * 60));
+        manager.handle(None).unwrap();
+        assert!(manager.tracker.is_paused());
+        assert!(manager.status().paused);
+        // the time they were away doesn't count
+        let remaining = Duration::from_secs(15 * 60);
+        assert_eq!(manager.tracker.time_remaining(), Some(remaining));
+        clock.advance(Duration::from_secs(30 * 60));
+        idle.set(Duration::from_secs(35 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.tracker.time_remaining(), Some(remaining));
+        idle.set(Duration::from_secs(0));
+        manager.handle(None).unwrap();
+        assert!(!manager.tracker.is_paused());
+        clock.advance(remaining);
+        manager.handle(None).unwrap();
+        assert_eq!(manager.tracker.state, State::PendingShortBreak);
+        let history = std::fs::read_to_string(history_path()).unwrap();
+        assert!(history.contains("\"paused\":2100"));
+        assert!(history.contains("\"outcome\":\"completed\""));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_abandons_work_while_away() {
+        let (config, idle) = idle_config(IdleAction::Abandon);
+        let (mut manager, clock, light) = create_manager_with(config);
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(10 * 60));
+        idle.set(Duration::from_secs(5 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.tracker.state, State::PendingWork);
+        assert_eq!(manager.timeout(), None);
+        assert_eq!(*light.colors.borrow(), vec![GREEN, RED]);
+        let history = std::fs::read_to_string(history_path()).unwrap();
+        assert!(history.contains("\"outcome\":\"interrupted\""));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_keeps_a_requested_pause_when_the_user_returns() {
+        let (config, idle) = idle_config(IdleAction::Pause);
+        let (mut manager, clock, _) = create_manager_with(config);
+        manager.handle(PRIMARY).unwrap();
+        manager.handle(Some(Command::Pause)).unwrap();
+        assert!(manager.tracker.is_paused());
+        clock.advance(Duration::from_secs(60));
+        idle.set(Duration::from_secs(0));
+        manager.handle(None).unwrap();
+        assert!(manager.tracker.is_paused());
+        manager.handle(Some(Command::Pause)).unwrap();
+        assert!(!manager.tracker.is_paused());
+        assert_eq!(
+            manager.tracker.time_remaining(),
+            Some(Duration::from_secs(20 * 60))
+        );
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_ignores_idle_time_outside_work() {
+        let (config, idle) = idle_config(IdleAction::Abandon);
+        let (mut manager, clock, _) = create_manager_with(config);
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        manager.handle(PRIMARY).unwrap();
+        idle.set(Duration::from_secs(10 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.tracker.state, State::ShortBreak);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_enables_do_not_disturb_while_working() {
+        let dnd = FakeDnd::default();
+        let (mut manager, clock, _) = create_manager_with(ManagerConfig {
+            dnd: Some(Box::new(dnd.clone())),
+            ..config()
+        });
+        manager.handle(None).unwrap();
+        assert!(dnd.calls.borrow().is_empty());
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(*dnd.calls.borrow(), vec![true]);
+        clock.advance(Duration::from_secs(60));
+        manager.handle(None).unwrap();
+        assert_eq!(*dnd.calls.borrow(), vec![true]);
+        clock.advance(Duration::from_secs(19 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(*dnd.calls.borrow(), vec![true, false]);
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(*dnd.calls.borrow(), vec![true, false]);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_blocks_sites_while_working() {
+        let hosts_path = history_path().with_extension("hosts");
+        let hosts = "127.0.0.1 localhost\n";
+        let leftover = "# BEGIN tomatina\n0.0.0.0 example.com\n# END tomatina\n";
+        std::fs::write(&hosts_path, format!("{}{}", hosts, leftover)).unwrap();
+        let (mut manager, clock, _) = create_manager_with(ManagerConfig {
+            blocker: Some(HostsBlocker::new(BlockConfig {
+                domains: vec!["example.com".to_string()],
+                hosts_file: hosts_path.clone(),
+                dry_run: false,
+            })),
+            ..config()
+        });
+        let read_hosts = || std::fs::read_to_string(&hosts_path).unwrap();
+        assert_eq!(read_hosts(), hosts);
+        manager.handle(PRIMARY).unwrap();
+        assert!(read_hosts().contains("0.0.0.0 example.com\n"));
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(read_hosts(), hosts);
+        std::fs::remove_file(&hosts_path).unwrap();
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_shares_phases_with_followers() {
+        let timeout = Some(Duration::from_secs(5));
+        let mut leader_signal = ButtonSignal::in_process().unwrap();
+        let mut follower_signal = ButtonSignal::in_process().unwrap();
+        let (mut leader, _, _) = create_manager();
+        let session = Session::start(
+            &SyncConfig::Listen("127.0.0.1:0".to_string()),
+            leader_signal.sender(),
+        )
+        .unwrap();
+        let addr = match &session {
+            Session::Leader(leader) => leader.addr(),
+            Session::Follower(_) => unreachable!(),
+        };
+        leader.sync = Some(session);
+        leader.handle(PRIMARY).unwrap();
+        let (mut follower, _, follower_light) = create_manager();
+        follower.sync = Some(
+            Session::start(
+                &SyncConfig::Leader(addr.to_string()),
+                follower_signal.sender(),
+            )
+            .unwrap(),
+        );
+        follower
+            .handle(follower_signal.wait(timeout).unwrap())
+            .unwrap();
+        assert_eq!(follower.tracker.state, State::Working);
+        // pressing the follower's button moves everyone on
+        follower.handle(PRIMARY).unwrap();
+        assert_eq!(follower.tracker.state, State::Working);
+        leader.handle(leader_signal.wait(timeout).unwrap()).unwrap();
+        assert_eq!(leader.tracker.state, State::ShortBreak);
+        follower
+            .handle(follower_signal.wait(timeout).unwrap())
+            .unwrap();
+        assert_eq!(follower.tracker.state, State::ShortBreak);
+        assert_eq!(*follower_light.colors.borrow(), vec![GREEN, BLUE]);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_shares_pauses_with_followers() {
+        let timeout = Some(Duration::from_secs(5));
+        let mut follower_signal = ButtonSignal::in_process().unwrap();
+        let (mut leader, leader_clock, _) = create_manager();
+        let session = Session::start(
+            &SyncConfig::Listen("127.0.0.1:0".to_string()),
+            ButtonSignal::in_process().unwrap().sender(),
+        )
+        .unwrap();
+        let addr = match &session {
+            Session::Leader(leader) => leader.addr(),
+            Session::Follower(_) => unreachable!(),
+        };
+        leader.sync = Some(session);
+        leader.handle(PRIMARY).unwrap();
+        let (mut follower, follower_clock, _) = create_manager();
+        follower.sync = Some(
+            Session::start(
+                &SyncConfig::Leader(addr.to_string()),
+                follower_signal.sender(),
+            )
+            .unwrap(),
+        );
+        follower
+            .handle(follower_signal.wait(timeout).unwrap())
+            .unwrap();
+        leader_clock.advance(Duration::from_secs(60));
+        leader.handle(Some(Command::Pause)).unwrap();
+        follower
+            .handle(follower_signal.wait(timeout).unwrap())
+            .unwrap();
+        assert!(follower.tracker.is_paused());
+        // the follower's clock stands still with the leader's
+        follower_clock.advance(Duration::from_secs(5 * 60));
+        assert_eq!(
+            follower.tracker.time_remaining(),
+            leader.tracker.time_remaining()
+        );
+        leader.handle(Some(Command::Pause)).unwrap();
+        follower
+            .handle(follower_signal.wait(timeout).unwrap())
+            .unwrap();
+        assert!(!follower.tracker.is_paused());
+        assert_eq!(follower.tracker.state, State::Working);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_sends_events() {
+        let (mut manager, clock, _) = create_manager();
+        let events = manager.events.subscribe();
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(19 * 60));
+        manager.handle(None).unwrap();
+        clock.advance(Duration::from_secs(60));
+        manager.handle(None).unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::PressReceived {
+                    button: ButtonPress::Primary
+                },
+                Event::StateChanged {
+                    from: State::PendingWork,
+                    to: State::Working,
+                    intervals: 0
+                },
+                Event::Tick {
+                    state: State::Working,
+                    remaining_secs: 20 * 60
+                },
+                Event::Tick {
+                    state: State::Working,
+                    remaining_secs: 60
+                },
+                Event::Warning {
+                    state: State::Working,
+                    remaining_secs: 60
+                },
+                Event::StateChanged {
+                    from: State::Working,
+                    to: State::PendingShortBreak,
+                    intervals: 1
+                },
+            ]
+        );
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_sends_events_after_a_late_wakeup() {
+        let (mut manager, clock, _) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        let events = manager.events.subscribe();
+        clock.advance(Duration::from_millis(60_300));
+        manager.handle(None).unwrap();
+        clock.advance(Duration::from_secs(1));
+        manager.handle(None).unwrap();
+        clock.advance(Duration::from_millis(1_108_700));
+        manager.handle(None).unwrap();
+        clock.advance(Duration::from_secs(10));
+        manager.handle(None).unwrap();
+        assert_eq!(
+            events.try_iter().collect::<Vec<_>>(),
+            vec![
+                Event::Tick {
+                    state: State::Working,
+                    remaining_secs: 19 * 60
+                },
+                Event::Tick {
+                    state: State::Working,
+                    remaining_secs: 60
+                },
+                Event::Warning {
+                    state: State::Working,
+                    remaining_secs: 30
+                },
+            ]
+        );
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_counts_progress_from_earlier_runs() {
+        let (mut manager, clock, _) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        let (manager, _, _) = create_manager_with(ManagerConfig {
+            daily_goal: Some(4),
+            ..config()
+        });
+        let status = manager.status();
+        assert_eq!((status.completed_today, status.daily_goal), (1, Some(4)));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_keeps_the_phase_on_shutdown() {
+        let (mut manager, clock, light) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(5 * 60));
+        manager.shutdown().unwrap();
+        let records = History::new(history_path()).read().unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].state, State::Working);
+        assert_eq!(records[0].outcome, Outcome::Interrupted);
+        assert_eq!(*light.colors.borrow(), vec![GREEN]);
+        assert!(!status_path().exists());
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_reloads_the_config_file() {
+        let config_path = history_path().with_extension("toml");
+        std::fs::write(&config_path, "[hooks]\ntimeout_secs = 3\n").unwrap();
+        let (mut manager, _, _) = create_manager_with(ManagerConfig {
+            config_path: Some(config_path.clone()),
+            ..config()
+        });
+        manager.handle(Some(Command::Reload)).unwrap();
+        assert_eq!(manager.config.hooks.timeout_secs, 3);
+        std::fs::remove_file(config_path).unwrap();
+        cleanup();
+    }
+
+    fn schedule(toml: &str) -> Option<Schedule> {
+        Some(toml::from_str(toml).unwrap())
+    }
+
+    #[test]
+    fn test_manager_switches_off_outside_working_hours() {
+        // the mock clock starts at noon on a Monday
+        let (mut manager, clock, light) = create_manager_with(ManagerConfig {
+            schedule: schedule(r#"mon = "11:00-12:10""#),
+            ..config()
+        });
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(manager.tracker.state, State::Working);
+        clock.advance(Duration::from_secs(10 * 60));
+        manager.handle(None).unwrap(); // 12:10
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(manager.tracker.state, State::Working);
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(60)));
+        clock.advance(Duration::from_secs(10 * 60));
+        manager.handle(None).unwrap(); // 12:20
+        assert_eq!(manager.tracker.state, State::PendingShortBreak);
+        // working hours start again next Monday
+        assert_eq!(
+            manager.timeout(),
+            Some(Duration::from_secs(7 * 24 * 60 * 60 - 80 * 60))
+        );
+        assert_eq!(*light.colors.borrow(), vec![GREEN, OFF]);
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_can_end_the_set_outside_working_hours() {
+        let (mut manager, clock, light) = create_manager_with(ManagerConfig {
+            schedule: schedule(
+                r#"
+                mon = "11:00-17:00"
+                lunch = "12:10-13:00"
+                outside = "end"
+                "#,
+            ),
+            ..config()
+        });
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(10 * 60));
+        manager.handle(None).unwrap(); // 12:10
+        assert_eq!(manager.tracker.state, State::PendingWork);
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(50 * 60)));
+        clock.advance(Duration::from_secs(50 * 60));
+        manager.handle(None).unwrap(); // 13:00
+        assert_eq!(*light.colors.borrow(), vec![GREEN, OFF, RED]);
+        let history = std::fs::read_to_string(history_path()).unwrap();
+        assert!(history.contains("\"interrupted\""));
+        cleanup();
+    }
+
+    #[test]
+    fn test_manager_escalates_color_when_left_pending() {
+        let (mut manager, clock, light) = create_manager_with(ManagerConfig {
+            overtime_colors: true,
+            ..config()
+        });
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(20 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(5 * 60)));
+        clock.advance(Duration::from_secs(5 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.timeout(), Some(Duration::from_secs(5 * 60)));
+        clock.advance(Duration::from_secs(10 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.timeout(), None);
+        manager.handle(PRIMARY).unwrap();
+        assert_eq!(
+            *light.colors.borrow(),
+            vec![GREEN, RED, YELLOW, HOT_PINK, BLUE]
+        );
+        cleanup();
+    }
+
+    #[test]
+    fn test_overtime_colors_still_show_a_press() {
+        for (_, color) in &OVERTIME_COLORS {
+            assert_ne!(*color, PRESSED_COLOR);
+        }
+    }
+
+    #[test]
+    fn test_manager_does_not_escalate_color_by_default() {
+        let (mut manager, clock, light) = create_manager();
+        manager.handle(PRIMARY).unwrap();
+        clock.advance(Duration::from_secs(40 * 60));
+        manager.handle(None).unwrap();
+        assert_eq!(manager.timeout(), None);
+        assert_eq!(*light.colors.borrow(), vec![GREEN, RED]);
+        cleanup();
+    }
 
     #[test]
     fn test_loggable_time_remaining_returns_None_if_not_near_a_multiple_of_1_minute() {
@@ -146,4 +1421,24 @@ mod tests {
             Some(Duration::from_millis(120_000)),
         );
     }
+
+    #[test]
+    fn test_time_until_next_log_wakes_at_the_next_whole_minute() {
+        assert_eq!(
+            time_until_next_log(Duration::from_millis(90_000)),
+            Duration::from_millis(30_000)
+        );
+        assert_eq!(
+            time_until_next_log(Duration::from_millis(59_000)),
+            Duration::from_millis(59_000)
+        );
+    }
+
+    #[test]
+    fn test_time_until_next_log_waits_a_full_minute_when_on_a_minute() {
+        assert_eq!(
+            time_until_next_log(Duration::from_millis(120_000)),
+            Duration::from_millis(60_000)
+        );
+    }
 }
@@ -0,0 +1,391 @@
+use crate::control::{ButtonPress, Command, Sender};
+use crate::error::{Error, Result};
+use crate::events::{Broadcaster, Event};
+use crate::tracker::State;
+
+use log::{info, warn};
+use rumqttc::{Client, MqttOptions, Packet, Publish, QoS};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+const KEEP_ALIVE: Duration = Duration::from_secs(60);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+/// Messages queued for the broker before any more are dropped.
+const QUEUE_CAPACITY: usize = 64;
+
+/// The `[mqtt]` section of the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MqttConfig {
+    pub broker: String,
+    pub client_id: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Topics are published under this, and commands read from `<prefix>/command`.
+    pub topic_prefix: String,
+    /// Announce sensors and buttons to Home Assistant.
+    pub discovery: bool,
+    pub discovery_prefix: String,
+}
+
+impl Default for MqttConfig {
+    fn default() -> Self {
+        Self {
+            broker: "localhost:1883".to_string(),
+            client_id: "tomatina".to_string(),
+            username: None,
+            password: None,
+            topic_prefix: "tomatina".to_string(),
+            discovery: false,
+            discovery_prefix: "homeassistant".to_string(),
+        }
+    }
+}
+
+impl MqttConfig {
+    fn topic(&self, name: &str) -> String {
+        format!("{}/{}", self.topic_prefix, name)
+    }
+
+    /// Checks what the config file format can't.
+    pub fn check(&self) -> std::result::Result<(), String> {
+        if self.password.is_some() && self.username.is_none() {
+            return Err("an MQTT password needs a username to go with it".to_string());
+        }
+        self.host_and_port().map(drop)
+    }
+
+    /// Splits `broker` into a host and a port, which defaults to 1883.
+    fn host_and_port(&self) -> std::result::Result<(String, u16), String> {
+        let (host, port) = match self.broker.rsplit_once(':') {
+            Some((host, port)) if !host.ends_with(':') => (host, port),
+            _ => (self.broker.as_str(), "1883"),
+        };
+        let port = port
+            .parse()
+            .map_err(|_| format!("invalid MQTT broker address {:?}", self.broker))?;
+        Ok((
+            host.trim_start_matches('[')
+                .trim_end_matches(']')
+                .to_string(),
+            port,
+        ))
+    }
+}
+
+/// Publishes the state to an MQTT broker and takes commands from it,
+/// reconnecting whenever the connection drops.
+pub struct Mqtt {
+    config: MqttConfig,
+    client: Client,
+    /// Republished on reconnecting, in case the broker lost it.
+    state: Mutex<State>,
+}
+
+impl Mqtt {
+    pub fn start(
+        config: MqttConfig,
+        state: State,
+        inbox: Sender,
+        events: &Broadcaster,
+    ) -> Result<()> {
+        let (host, port) = config.host_and_port().map_err(Error::Config)?;
+        let mut options = MqttOptions::new(config.client_id.clone(), host, port);
+        options.set_keep_alive(KEEP_ALIVE).set_clean_session(true);
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.clone().unwrap_or_default());
+        }
+        let (client, mut connection) = Client::new(options, QUEUE_CAPACITY);
+        let mqtt = Arc::new(Self {
+            config,
+            client,
+            state: Mutex::new(state),
+        });
+        let events = events.subscribe();
+        let publisher = mqtt.clone();
+        thread::spawn(move || {
+            for event in events {
+                publisher.publish_event(&event);
+            }
+        });
+        thread::spawn(move || {
+            // going through the connection's events is what keeps it up, and
+            // reconnects it when it drops
+            for event in connection.iter() {
+                match event {
+                    Ok(rumqttc::Event::Incoming(Packet::ConnAck(_))) => mqtt.on_connect(),
+                    Ok(rumqttc::Event::Incoming(Packet::Publish(publish))) => {
+                        mqtt.on_publish(&publish, &inbox)
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        warn!("MQTT connection to {} failed: {}", mqtt.config.broker, e);
+                        sleep(RECONNECT_INTERVAL);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+
+    fn on_connect(&self) {
+        info!("Connected to MQTT broker {}", self.config.broker);
+        if let Err(e) = self
+            .client
+            .try_subscribe(self.config.topic("command"), QoS::AtMostOnce)
+        {
+            warn!("failed to subscribe to MQTT commands: {}", e);
+        }
+        if self.config.discovery {
+            self.announce();
+        }
+        let state = *self.state.lock().unwrap();
+        self.publish(&self.config.topic("state"), &format!("{:?}", state), true);
+    }
+
+    fn on_publish(&self, publish: &Publish, inbox: &Sender) {
+        if publish.topic != self.config.topic("command") {
+            return;
+        }
+        match String::from_utf8_lossy(&publish.payload).trim() {
+            "next" => inbox.send(Command::Press(ButtonPress::Primary)),
+            "pause" => inbox.send(Command::Pause),
+            other => warn!("unexpected MQTT command: {:?}", other),
+        }
+    }
+
+    fn publish_event(&self, event: &Event) {
+        match event {
+            Event::StateChanged {
+                from,
+                to,
+                intervals,
+            } => {
+                *self.state.lock().unwrap() = *to;
+                self.publish(&self.config.topic("state"), &format!("{:?}", to), true);
+                let transition = json!({ "from": from, "to": to, "intervals": intervals });
+                self.publish(
+                    &self.config.topic("transition"),
+                    &transition.to_string(),
+                    false,
+                );
+                if let State::PendingWork | State::PendingShortBreak | State::PendingLongBreak = to
+                {
+                    self.publish(&self.config.topic("time_remaining"), "0", true);
+                }
+            }
+            Event::Tick { remaining_secs, .. } => {
+                self.publish(
+                    &self.config.topic("time_remaining"),
+                    &remaining_secs.to_string(),
+                    true,
+                );
+            }
+            Event::Warning { .. } | Event::PressReceived { .. } => {}
+        }
+    }
+
+    /// Tells Home Assistant about our sensors and buttons.
+    fn announce(&self) {
+        let id = &self.config.client_id;
+        let device = json!({ "identifiers": [id], "name": "Tomatina" });
+        for name in ["state", "time_remaining"].iter() {
+            let mut config = json!({
+                "name": name.replace('_', " "),
+                "unique_id": format!("{}_{}", id, name),
+                "state_topic": self.config.topic(name),
+                "device": device,
+            });
+            if *name == "time_remaining" {
+                config["device_class"] = json!("duration");
+                config["unit_of_measurement"] = json!("s");
+            }
+            let topic = format!(
+                "{}/sensor/{}/{}/config",
+                self.config.discovery_prefix, id, name
+            );
+            self.publish(&topic, &config.to_string(), true);
+        }
+        for name in ["next", "pause"].iter() {
+            let config = json!({
+                "name": name,
+                "unique_id": format!("{}_{}", id, name),
+                "command_topic": self.config.topic("command"),
+                "payload_press": name,
+                "device": device,
+            });
+            let topic = format!(
+                "{}/button/{}/{}/config",
+                self.config.discovery_prefix, id, name
+            );
+            self.publish(&topic, &config.to_string(), true);
+        }
+    }
+
+    /// Publishes at most once, dropping the message if too many are waiting
+    /// for the broker.
+    fn publish(&self, topic: &str, payload: &str, retain: bool) {
+        if let Err(e) = self
+            .client
+            .try_publish(topic, QoS::AtMostOnce, retain, payload)
+        {
+            warn!("failed to publish to {}: {}", topic, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::{ButtonPress, ButtonSignal};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    const CONNECT: u8 = 0x10;
+    const CONNACK: u8 = 0x20;
+    const PUBLISH: u8 = 0x30;
+    const SUBSCRIBE: u8 = 0x82;
+
+    fn put_string(buf: &mut Vec<u8>, value: &str) {
+        buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn write_packet(out: &mut TcpStream, kind: u8, body: &[u8]) {
+        assert!(body.len() < 128);
+        out.write_all(&[kind, body.len() as u8]).unwrap();
+        out.write_all(body).unwrap();
+    }
+
+    fn read_packet(input: &mut TcpStream) -> (u8, Vec<u8>) {
+        let mut byte = [0];
+        input.read_exact(&mut byte).unwrap();
+        let kind = byte[0];
+        let mut len = 0;
+        for shift in (0..4).map(|i| 7 * i) {
+            input.read_exact(&mut byte).unwrap();
+            len |= ((byte[0] & 0x7f) as usize) << shift;
+            if byte[0] & 0x80 == 0 {
+                break;
+            }
+        }
+        let mut body = vec![0; len];
+        input.read_exact(&mut body).unwrap();
+        (kind, body)
+    }
+
+    /// Reads the next publish the broker receives, as (topic, payload, retain).
+    fn next_publish(client: &mut TcpStream) -> (String, String, bool) {
+        loop {
+            let (kind, body) = read_packet(client);
+            if kind & 0xf0 == PUBLISH {
+                let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                let payload = String::from_utf8(body[2 + topic_len..].to_vec()).unwrap();
+                return (topic, payload, kind & 1 == 1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_config_checks_credentials_and_address() {
+        let config = MqttConfig {
+            password: Some("secret".to_string()),
+            ..Default::default()
+        };
+        assert!(config.check().is_err());
+        let config = MqttConfig {
+            broker: "[::1]:1884".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(config.host_and_port(), Ok(("::1".to_string(), 1884)));
+        let config = MqttConfig {
+            broker: "broker.local".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.host_and_port(),
+            Ok(("broker.local".to_string(), 1883))
+        );
+    }
+
+    #[test]
+    fn test_client_publishes_state_and_takes_commands() {
+        // a broker that just talks to one client
+        let broker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let config = MqttConfig {
+            broker: broker.local_addr().unwrap().to_string(),
+            discovery: true,
+            ..Default::default()
+        };
+        let mut signal = ButtonSignal::in_process().unwrap();
+        let events = Broadcaster::default();
+        Mqtt::start(config, State::PendingWork, signal.sender(), &events).unwrap();
+        let (mut client, _) = broker.accept().unwrap();
+
+        let (kind, body) = read_packet(&mut client);
+        assert_eq!(kind, CONNECT);
+        assert_eq!(&body[..6], b"\x00\x04MQTT");
+        write_packet(&mut client, CONNACK, &[0, 0]);
+        let (kind, body) = read_packet(&mut client);
+        assert_eq!(kind, SUBSCRIBE);
+        assert_eq!(&body[2..], b"\x00\x10tomatina/command\x00");
+
+        let (topic, payload, retain) = next_publish(&mut client);
+        assert_eq!(topic, "homeassistant/sensor/tomatina/state/config");
+        assert!(payload.contains("\"state_topic\":\"tomatina/state\""));
+        assert!(retain);
+        for _ in 0..3 {
+            next_publish(&mut client);
+        }
+        assert_eq!(
+            next_publish(&mut client),
+            (
+                "tomatina/state".to_string(),
+                "PendingWork".to_string(),
+                true
+            )
+        );
+
+        events.send(Event::StateChanged {
+            from: State::PendingWork,
+            to: State::Working,
+            intervals: 0,
+        });
+        assert_eq!(
+            next_publish(&mut client),
+            ("tomatina/state".to_string(), "Working".to_string(), true)
+        );
+        let (topic, payload, retain) = next_publish(&mut client);
+        assert_eq!(topic, "tomatina/transition");
+        assert_eq!(
+            payload,
+            "{\"from\":\"PendingWork\",\"intervals\":0,\"to\":\"Working\"}"
+        );
+        assert!(!retain);
+        events.send(Event::Tick {
+            state: State::Working,
+            remaining_secs: 1200,
+        });
+        assert_eq!(
+            next_publish(&mut client),
+            (
+                "tomatina/time_remaining".to_string(),
+                "1200".to_string(),
+                true
+            )
+        );
+
+        let mut command = Vec::new();
+        put_string(&mut command, "tomatina/command");
+        command.extend_from_slice(b"next");
+        write_packet(&mut client, PUBLISH, &command);
+        assert_eq!(
+            signal.wait(Some(Duration::from_secs(5))).unwrap(),
+            Some(Command::Press(ButtonPress::Primary))
+        );
+    }
+}
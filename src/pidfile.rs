@@ -0,0 +1,186 @@
+use crate::error::{Error, Result};
+use crate::runtime;
+
+use log::info;
+use std::fs;
+use std::io::{self, Seek, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+/// How long to wait for a running tomatina to exit when taking over from it.
+const TAKEOVER_TIMEOUT: Duration = Duration::from_secs(10);
+const TAKEOVER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Holds a lock on a file with our PID in it for as long as it's kept, so
+/// that a second instance doesn't fight us over the pipe and the button.
+pub struct PidFile {
+    path: PathBuf,
+    // the lock goes with the open file
+    _file: fs::File,
+}
+
+impl PidFile {
+    pub fn default_path() -> PathBuf {
+        runtime::dir().join("tomatina.pid")
+    }
+
+    /// Takes the lock. If another process has it, either asks that one to
+    /// exit and waits for it to, when `takeover` is set, or fails.
+    pub fn acquire(path: &Path, takeover: bool) -> Result<Self> {
+        if let Some(pid_file) = Self::try_acquire(path)? {
+            return Ok(pid_file);
+        }
+        let pid = match read_pid(path) {
+            Some(pid) if takeover => pid,
+            Some(pid) => {
+                return Err(Error::AlreadyRunning(format!(
+                    "tomatina is already running (pid {}), use --takeover to replace it",
+                    pid
+                )))
+            }
+            None => {
+                return Err(Error::AlreadyRunning(
+                    "tomatina is already running".to_string(),
+                ))
+            }
+        };
+        info!("Asking tomatina (pid {}) to hand over", pid);
+        if unsafe { libc::kill(pid, libc::SIGTERM) } != 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ESRCH) {
+                return Err(Error::io("stop the running tomatina")(err));
+            }
+        }
+        let deadline = Instant::now() + TAKEOVER_TIMEOUT;
+        while Instant::now() < deadline {
+            sleep(TAKEOVER_POLL_INTERVAL);
+            if let Some(pid_file) = Self::try_acquire(path)? {
+                return Ok(pid_file);
+            }
+        }
+        Err(Error::AlreadyRunning(format!(
+            "tomatina (pid {}) didn't exit",
+            pid
+        )))
+    }
+
+    fn try_acquire(path: &Path) -> io::Result<Option<Self>> {
+        loop {
+            let mut file = fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)?;
+            if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() == io::ErrorKind::WouldBlock {
+                    return Ok(None);
+                }
+                return Err(err);
+            }
+            // the previous holder removes the file on its way out, which
+            // would leave us holding a lock nobody else can see
+            let locked = file.metadata()?;
+            match fs::metadata(path) {
+                Ok(current) if (current.dev(), current.ino()) == (locked.dev(), locked.ino()) => {}
+                _ => continue,
+            }
+            file.set_len(0)?;
+            file.rewind()?;
+            writeln!(file, "{}", std::process::id())?;
+            return Ok(Some(Self {
+                path: path.to_path_buf(),
+                _file: file,
+            }));
+        }
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// The PID of the process holding the lock, as long as it's one that can be
+/// signalled on its own: 0 and -1 stand for whole groups of processes.
+fn read_pid(path: &Path) -> Option<libc::pid_t> {
+    let pid: libc::pid_t = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(pid).filter(|pid| *pid > 0 && *pid != std::process::id() as libc::pid_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tomatina-{}-{}.pid", name, std::process::id()))
+    }
+
+    /// Opens the file separately from any `PidFile` and locks it as another
+    /// instance would.
+    fn lock(path: &Path, pid: &str) -> fs::File {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .unwrap();
+        assert_eq!(unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) }, 0);
+        writeln!(file, "{}", pid).unwrap();
+        file
+    }
+
+    #[test]
+    fn test_second_instance_is_refused() {
+        let path = path("refused");
+        let pid_file = PidFile::acquire(&path, false).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        assert_eq!(contents, format!("{}\n", std::process::id()));
+        // flock locks belong to the open file, so a second open conflicts
+        // even within one process
+        let err = PidFile::acquire(&path, false).err().unwrap();
+        assert!(matches!(err, Error::AlreadyRunning(_)));
+        drop(pid_file);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_takeover_stops_the_running_instance() {
+        let path = path("takeover");
+        let file = lock(&path, "");
+        // the child shares the locked file, so the lock goes when it does
+        let mut running = Command::new("sleep")
+            .arg("30")
+            .stdin(Stdio::from(file.try_clone().unwrap()))
+            .spawn()
+            .unwrap();
+        drop(file);
+        let pid = running.id() as libc::pid_t;
+        fs::write(&path, format!("{}\n", pid)).unwrap();
+        let err = PidFile::acquire(&path, false).err().unwrap();
+        assert!(err.to_string().contains(&pid.to_string()));
+        let pid_file = PidFile::acquire(&path, true).unwrap();
+        assert!(!running.wait().unwrap().success());
+        drop(pid_file);
+    }
+
+    #[test]
+    fn test_takeover_only_signals_a_single_other_process() {
+        let path = path("groups");
+        let own = std::process::id().to_string();
+        for pid in &["0", "-1", own.as_str()] {
+            let file = lock(&path, pid);
+            assert_eq!(read_pid(&path), None);
+            let err = PidFile::acquire(&path, true).err().unwrap();
+            assert!(matches!(err, Error::AlreadyRunning(_)));
+            drop(file);
+        }
+        fs::remove_file(path).unwrap();
+    }
+}
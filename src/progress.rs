@@ -0,0 +1,108 @@
+use crate::history::{Outcome, Record};
+use crate::tracker::State;
+
+use chrono::{Local, NaiveDate, TimeZone};
+use std::convert::TryFrom;
+
+/// Completed work intervals for the current day, carried across restarts by
+/// counting them from the history.
+pub struct DailyProgress {
+    date: NaiveDate,
+    completed: u32,
+}
+
+impl DailyProgress {
+    pub fn new(records: &[Record], today: NaiveDate) -> Self {
+        let mut progress = Self {
+            date: today,
+            completed: 0,
+        };
+        for record in records {
+            progress.add(record);
+        }
+        progress
+    }
+
+    /// Counts the record if it is a work interval completed on or after the
+    /// current day, moving on to a new day if need be. Returns whether it counted.
+    pub fn add(&mut self, record: &Record) -> bool {
+        if record.state != State::Working || record.outcome != Outcome::Completed {
+            return false;
+        }
+        // the history can be edited by hand, so its times may be nonsense
+        let date = match local_date(record.ended) {
+            Some(date) => date,
+            None => return false,
+        };
+        if date < self.date {
+            return false;
+        }
+        if date > self.date {
+            self.date = date;
+            self.completed = 0;
+        }
+        self.completed += 1;
+        true
+    }
+
+    pub fn completed_on(&self, date: NaiveDate) -> u32 {
+        if date == self.date {
+            self.completed
+        } else {
+            0
+        }
+    }
+}
+
+/// The local date at `secs` since the Unix epoch, if that's a date at all.
+pub fn local_date(secs: u64) -> Option<NaiveDate> {
+    let secs = i64::try_from(secs).ok()?;
+    Some(Local.timestamp_opt(secs, 0).single()?.date_naive())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DAY: u64 = 24 * 60 * 60;
+    // midday on 2020-09-13 in most timezones
+    const NOON: u64 = 1_599_998_400;
+
+    fn work(ended: u64, outcome: Outcome) -> Record {
+        Record {
+            state: State::Working,
+            started: ended - 25 * 60,
+            ended,
+            outcome,
+            task: None,
+            paused: 0,
+        }
+    }
+
+    #[test]
+    fn test_new_counts_completed_work_from_today_only() {
+        let records = vec![
+            work(NOON - DAY, Outcome::Completed),
+            work(NOON, Outcome::Completed),
+            work(NOON + 60, Outcome::Interrupted),
+            Record {
+                state: State::ShortBreak,
+                ..work(NOON + 120, Outcome::Completed)
+            },
+            work(NOON + 180, Outcome::Completed),
+        ];
+        let progress = DailyProgress::new(&records, local_date(NOON).unwrap());
+        assert_eq!(progress.completed_on(local_date(NOON).unwrap()), 2);
+        assert_eq!(progress.completed_on(local_date(NOON + DAY).unwrap()), 0);
+    }
+
+    #[test]
+    fn test_add_starts_a_new_day() {
+        let mut progress = DailyProgress::new(&[], local_date(NOON).unwrap());
+        assert!(progress.add(&work(NOON, Outcome::Completed)));
+        assert!(progress.add(&work(NOON + DAY, Outcome::Completed)));
+        assert_eq!(progress.completed_on(local_date(NOON).unwrap()), 0);
+        assert_eq!(progress.completed_on(local_date(NOON + DAY).unwrap()), 1);
+        assert!(!progress.add(&work(u64::MAX, Outcome::Completed)));
+    }
+}
@@ -0,0 +1,78 @@
+use crate::error::{Error, Result};
+
+use std::env;
+use std::fs::{self, DirBuilder};
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::path::{Path, PathBuf};
+
+/// Where the files a running tomatina shares with the commands that talk to
+/// it live: `$XDG_RUNTIME_DIR`, or a directory of the user's own in `/tmp` if
+/// there isn't one.
+pub fn dir() -> PathBuf {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(fallback_dir)
+}
+
+fn fallback_dir() -> PathBuf {
+    // not the platform's temporary directory, which on macOS differs between
+    // us and the Automator workflows that write to our pipe
+    Path::new("/tmp").join(format!("tomatina-{}", unsafe { libc::getuid() }))
+}
+
+/// Creates the directory if need be. Anyone could have made the one in `/tmp`
+/// before us, so that one has to be ours alone.
+pub fn prepare() -> Result<PathBuf> {
+    let dir = dir();
+    if env::var_os("XDG_RUNTIME_DIR").is_some() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&dir)
+            .map_err(Error::io("create the runtime directory"))?;
+    } else {
+        create_private(&dir)?;
+    }
+    Ok(dir)
+}
+
+fn create_private(dir: &Path) -> Result<()> {
+    match DirBuilder::new().mode(0o700).create(dir) {
+        Err(e) if e.kind() != io::ErrorKind::AlreadyExists => {
+            return Err(Error::io("create the runtime directory")(e))
+        }
+        _ => {}
+    }
+    // don't follow a symlink to somewhere else
+    let metadata = fs::symlink_metadata(dir).map_err(Error::io("check the runtime directory"))?;
+    if !metadata.is_dir()
+        || metadata.uid() != unsafe { libc::getuid() }
+        || metadata.mode() & 0o777 != 0o700
+    {
+        return Err(Error::Io(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!(
+                "{} should be a directory only you can use: remove it or set XDG_RUNTIME_DIR",
+                dir.display()
+            ),
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_shared_directory_must_be_private() {
+        let dir = env::temp_dir().join(format!("tomatina-runtime-{}", std::process::id()));
+        create_private(&dir).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(create_private(&dir).is_err());
+        fs::remove_dir(&dir).unwrap();
+    }
+}
@@ -0,0 +1,183 @@
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime};
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// What happens outside working hours. The button is switched off and presses
+/// are ignored either way.
+#[derive(Debug, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OffHours {
+    /// Let a running phase carry on.
+    Idle,
+    /// Stop a running phase, ending the set.
+    End,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Span {
+    start: NaiveTime,
+    end: NaiveTime,
+}
+
+impl Span {
+    fn contains(&self, time: NaiveTime) -> bool {
+        self.start <= time && time < self.end
+    }
+}
+
+impl FromStr for Span {
+    type Err = String;
+
+    /// Parses `"09:00-17:30"`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |time: &str| {
+            NaiveTime::parse_from_str(time.trim(), "%H:%M")
+                .map_err(|e| format!("invalid time {:?} in {:?}: {}", time, s, e))
+        };
+        let mut times = s.splitn(2, '-');
+        let start = parse(times.next().unwrap())?;
+        let end = parse(
+            times
+                .next()
+                .ok_or(format!("expected HH:MM-HH:MM, got {:?}", s))?,
+        )?;
+        if end <= start {
+            return Err(format!("{:?} ends before it starts", s));
+        }
+        Ok(Self { start, end })
+    }
+}
+
+/// Working hours for each day of the week, less a lunch break.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(try_from = "RawSchedule")]
+pub struct Schedule {
+    /// Indexed from Monday.
+    days: [Option<Span>; 7],
+    lunch: Option<Span>,
+    pub outside: OffHours,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSchedule {
+    mon: Option<String>,
+    tue: Option<String>,
+    wed: Option<String>,
+    thu: Option<String>,
+    fri: Option<String>,
+    sat: Option<String>,
+    sun: Option<String>,
+    lunch: Option<String>,
+    outside: Option<OffHours>,
+}
+
+impl TryFrom<RawSchedule> for Schedule {
+    type Error = String;
+
+    fn try_from(raw: RawSchedule) -> Result<Self, Self::Error> {
+        let parse = |span: Option<String>| span.map(|s| s.parse()).transpose();
+        Ok(Self {
+            days: [
+                parse(raw.mon)?,
+                parse(raw.tue)?,
+                parse(raw.wed)?,
+                parse(raw.thu)?,
+                parse(raw.fri)?,
+                parse(raw.sat)?,
+                parse(raw.sun)?,
+            ],
+            lunch: parse(raw.lunch)?,
+            outside: raw.outside.unwrap_or(OffHours::Idle),
+        })
+    }
+}
+
+impl Schedule {
+    pub fn is_working(&self, at: NaiveDateTime) -> bool {
+        let day = match self.day(at) {
+            Some(day) => day,
+            None => return false,
+        };
+        let time = at.time();
+        day.contains(time) && !self.lunch.is_some_and(|lunch| lunch.contains(time))
+    }
+
+    /// The next time after `at` that working hours start or stop, if any.
+    pub fn next_change(&self, at: NaiveDateTime) -> Option<NaiveDateTime> {
+        (0..=7)
+            .filter_map(|days| {
+                let date = at.date() + Duration::days(days);
+                let day = self.days[date.weekday().num_days_from_monday() as usize]?;
+                let mut times = vec![day.start, day.end];
+                if let Some(lunch) = self.lunch {
+                    times.extend(&[lunch.start, lunch.end]);
+                }
+                times
+                    .into_iter()
+                    .map(|time| date.and_time(time))
+                    .filter(|time| *time > at)
+                    .min()
+            })
+            .next()
+    }
+
+    fn day(&self, at: NaiveDateTime) -> Option<Span> {
+        self.days[at.weekday().num_days_from_monday() as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn schedule() -> Schedule {
+        toml::from_str::<Schedule>(
+            r#"
+            mon = "09:00-17:30"
+            tue = "09:00-17:30"
+            fri = "10:00-15:00"
+            lunch = "12:30-13:30"
+            "#,
+        )
+        .unwrap()
+    }
+
+    // 2020-09-14 was a Monday
+    fn at(day: u32, time: &str) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 9, day)
+            .unwrap()
+            .and_time(NaiveTime::parse_from_str(time, "%H:%M").unwrap())
+    }
+
+    #[test]
+    fn test_is_working_within_the_days_hours() {
+        let schedule = schedule();
+        assert!(!schedule.is_working(at(14, "08:59")));
+        assert!(schedule.is_working(at(14, "09:00")));
+        assert!(!schedule.is_working(at(14, "12:45")));
+        assert!(schedule.is_working(at(14, "17:29")));
+        assert!(!schedule.is_working(at(14, "17:30")));
+        assert!(!schedule.is_working(at(16, "10:00")));
+        assert_eq!(schedule.outside, OffHours::Idle);
+    }
+
+    #[test]
+    fn test_next_change_finds_the_next_boundary() {
+        let schedule = schedule();
+        assert_eq!(schedule.next_change(at(14, "07:00")), Some(at(14, "09:00")));
+        assert_eq!(schedule.next_change(at(14, "09:00")), Some(at(14, "12:30")));
+        assert_eq!(schedule.next_change(at(14, "12:30")), Some(at(14, "13:30")));
+        assert_eq!(schedule.next_change(at(14, "13:30")), Some(at(14, "17:30")));
+        assert_eq!(schedule.next_change(at(15, "18:00")), Some(at(18, "10:00")));
+    }
+
+    #[test]
+    fn test_invalid_hours_are_rejected() {
+        assert!(toml::from_str::<Schedule>(r#"mon = "17:00-09:00""#).is_err());
+        assert!(toml::from_str::<Schedule>(r#"mon = "9am""#).is_err());
+        assert!(toml::from_str::<Schedule>(r#"monday = "09:00-17:00""#).is_err());
+    }
+}
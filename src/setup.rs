@@ -0,0 +1,66 @@
+use crate::error::{Error, Result};
+
+use log::{info, warn};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Sorted before systemd's seat rules, which act on the `uaccess` tag.
+pub const UDEV_RULE_PATH: &str = "/etc/udev/rules.d/70-tomatina.rules";
+
+const UDEV_RULE: &str = include_str!("../config/linux/70-tomatina.rules");
+
+/// Installs the udev rule at `path` and applies it to a button that's
+/// already plugged in.
+pub fn linux(path: &Path) -> Result<()> {
+    fs::write(path, UDEV_RULE).map_err(|e| {
+        let hint = if e.kind() == io::ErrorKind::PermissionDenied {
+            ", try again with sudo"
+        } else {
+            ""
+        };
+        Error::Io(io::Error::new(
+            e.kind(),
+            format!("failed to write {}: {}{}", path.display(), e, hint),
+        ))
+    })?;
+    info!("Wrote {}", path.display());
+    for args in &[
+        &["control", "--reload-rules"][..],
+        // replaying "add" for every USB device would upset their drivers too
+        &[
+            "trigger",
+            "--action=add",
+            "--subsystem-match=usb",
+            "--attr-match=idVendor=d209",
+            "--attr-match=idProduct=1200",
+        ][..],
+    ] {
+        match Command::new("udevadm").args(*args).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => warn!("udevadm {} exited with {}", args.join(" "), status),
+            Err(e) => {
+                warn!("failed to run udevadm, replug the button instead: {}", e);
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::button::{USBBTN_PRODUCT_ID, USBBTN_VENDOR_ID};
+
+    #[test]
+    fn test_udev_rule_matches_the_button() {
+        let rule = UDEV_RULE
+            .lines()
+            .find(|line| !line.starts_with('#'))
+            .unwrap();
+        assert!(rule.contains(&format!("ATTR{{idVendor}}==\"{:04x}\"", USBBTN_VENDOR_ID)));
+        assert!(rule.contains(&format!("ATTR{{idProduct}}==\"{:04x}\"", USBBTN_PRODUCT_ID)));
+    }
+}
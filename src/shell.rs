@@ -0,0 +1,16 @@
+use std::io;
+use std::process::Command;
+
+/// Runs a helper program to completion and returns what it printed, treating a
+/// non-zero exit as an error.
+pub fn output(command: &mut Command) -> io::Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(format!(
+            "{:?} failed: {}",
+            command,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
@@ -0,0 +1,88 @@
+use crate::history::{Outcome, Record};
+use crate::tracker::State;
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq)]
+pub struct TaskTotal {
+    pub task: Option<String>,
+    pub intervals: u64,
+    pub time: Duration,
+}
+
+/// Totals completed work intervals per task, most time first.
+pub fn by_task(records: &[Record]) -> Vec<TaskTotal> {
+    let mut totals: HashMap<Option<&str>, TaskTotal> = HashMap::new();
+    for record in records {
+        if record.state != State::Working || record.outcome != Outcome::Completed {
+            continue;
+        }
+        let total = totals
+            .entry(record.task.as_deref())
+            .or_insert_with(|| TaskTotal {
+                task: record.task.clone(),
+                intervals: 0,
+                time: Duration::from_secs(0),
+            });
+        total.intervals += 1;
+        total.time += record.duration();
+    }
+    let mut totals: Vec<TaskTotal> = totals.into_values().collect();
+    totals.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| a.task.cmp(&b.task)));
+    totals
+}
+
+pub fn print(records: &[Record]) {
+    println!("{:>9} {:>7}  Task", "Intervals", "Minutes");
+    for total in by_task(records) {
+        println!(
+            "{:>9} {:>7}  {}",
+            total.intervals,
+            total.time.as_secs() / 60,
+            total.task.as_deref().unwrap_or("(no task)"),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(state: State, minutes: u64, outcome: Outcome, task: Option<&str>) -> Record {
+        Record {
+            state,
+            started: 0,
+            ended: minutes * 60,
+            outcome,
+            task: task.map(String::from),
+            paused: 0,
+        }
+    }
+
+    #[test]
+    fn test_by_task_totals_completed_work_per_task() {
+        let records = vec![
+            record(State::Working, 25, Outcome::Completed, Some("report")),
+            record(State::ShortBreak, 5, Outcome::Completed, None),
+            record(State::Working, 25, Outcome::Completed, None),
+            record(State::Working, 10, Outcome::Interrupted, Some("report")),
+            record(State::Working, 20, Outcome::Completed, Some("report")),
+        ];
+        assert_eq!(
+            by_task(&records),
+            vec![
+                TaskTotal {
+                    task: Some("report".to_string()),
+                    intervals: 2,
+                    time: Duration::from_secs(45 * 60),
+                },
+                TaskTotal {
+                    task: None,
+                    intervals: 1,
+                    time: Duration::from_secs(25 * 60),
+                },
+            ]
+        );
+    }
+}
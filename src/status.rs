@@ -0,0 +1,151 @@
+use crate::history::unix_secs;
+use crate::progress::local_date;
+use crate::runtime;
+use crate::tracker::State;
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// A snapshot of a running tomatina, written whenever it changes so other
+/// commands can report on it. Times are seconds since the Unix epoch.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub state: State,
+    /// When the current phase runs out.
+    pub ends: Option<u64>,
+    /// When the phase before the current pending state ran out.
+    pub overdue_since: Option<u64>,
+    pub task: Option<String>,
+    pub completed_today: u32,
+    pub daily_goal: Option<u32>,
+    /// Whether the clock is stopped on the current phase, leaving `ends` as of
+    /// `updated`.
+    #[serde(default)]
+    pub paused: bool,
+    pub updated: u64,
+}
+
+impl Status {
+    pub fn default_path() -> PathBuf {
+        runtime::dir().join("tomatina.status.json")
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    /// Replaces the file atomically so readers never see a partial write.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_string(self)?)?;
+        fs::rename(tmp, path)
+    }
+
+    pub fn describe(&self, now: SystemTime) -> String {
+        let now = unix_secs(now);
+        let mut description = format!("{:?}", self.state);
+        if let (Some(ends), true) = (self.ends, self.paused) {
+            let mins = ends.saturating_sub(self.updated) / 60;
+            description += &format!(", paused with {} mins remaining", mins);
+        } else if let Some(ends) = self.ends {
+            let mins = ends.saturating_sub(now) / 60;
+            description += &format!(", {} mins remaining", mins);
+        } else if let Some(since) = self.overdue_since {
+            let mins = now.saturating_sub(since) / 60;
+            description += &format!(", waiting for {} mins", mins);
+        }
+        if let Some(task) = &self.task {
+            description += &format!("\nTask: {}", task);
+        }
+        let completed = if local_date(self.updated) == local_date(now) {
+            self.completed_today
+        } else {
+            0
+        };
+        description += &match self.daily_goal {
+            Some(goal) => format!("\nToday: {}/{}", completed, goal),
+            None => format!("\nToday: {}", completed),
+        };
+        description
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    const NOW: u64 = 1_599_998_400;
+
+    fn status() -> Status {
+        Status {
+            state: State::Working,
+            ends: Some(NOW + 12 * 60 + 30),
+            overdue_since: None,
+            task: Some("write report".to_string()),
+            completed_today: 6,
+            daily_goal: Some(8),
+            paused: false,
+            updated: NOW - 60,
+        }
+    }
+
+    fn at(secs: u64) -> SystemTime {
+        SystemTime::UNIX_EPOCH + Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn test_describe_shows_time_remaining_and_progress() {
+        assert_eq!(
+            status().describe(at(NOW)),
+            "Working, 12 mins remaining\nTask: write report\nToday: 6/8"
+        );
+    }
+
+    #[test]
+    fn test_describe_shows_time_waiting() {
+        let status = Status {
+            state: State::PendingShortBreak,
+            ends: None,
+            overdue_since: Some(NOW - 3 * 60),
+            task: None,
+            daily_goal: None,
+            ..status()
+        };
+        assert_eq!(
+            status.describe(at(NOW)),
+            "PendingShortBreak, waiting for 3 mins\nToday: 6"
+        );
+    }
+
+    #[test]
+    fn test_describe_holds_time_remaining_while_paused() {
+        let status = Status {
+            paused: true,
+            ..status()
+        };
+        assert!(status
+            .describe(at(NOW + 10 * 60))
+            .starts_with("Working, paused with 13 mins remaining\n"));
+    }
+
+    #[test]
+    fn test_describe_resets_progress_on_a_new_day() {
+        assert!(status()
+            .describe(at(NOW + 24 * 60 * 60))
+            .ends_with("Today: 0/8"));
+    }
+
+    #[test]
+    fn test_status_round_trips_through_a_file() {
+        let path =
+            std::env::temp_dir().join(format!("tomatina-status-{}.json", std::process::id()));
+        status().write(&path).unwrap();
+        assert_eq!(Status::read(&path).unwrap(), status());
+        fs::remove_file(path).unwrap();
+    }
+}
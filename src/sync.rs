@@ -0,0 +1,292 @@
+use crate::control::{ButtonPress, Command, Sender};
+use crate::tracker::State;
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::{self, sleep};
+use std::time::{Duration, Instant};
+
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(5);
+/// How long a write to another instance may take before it's given up on.
+const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the leader is up to, so followers can line their own phase up with it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Update {
+    pub state: State,
+    pub elapsed_ms: u64,
+    /// Whether a pending state was reached by the previous phase running out.
+    pub overdue: bool,
+    pub intervals: u64,
+    #[serde(default)]
+    pub paused: bool,
+}
+
+impl Update {
+    fn aged(&self, by: Duration) -> Self {
+        if self.paused {
+            return self.clone();
+        }
+        Self {
+            elapsed_ms: self.elapsed_ms + by.as_millis() as u64,
+            ..self.clone()
+        }
+    }
+}
+
+/// What's sent between instances, one JSON object per line.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    /// From the leader.
+    Update(Update),
+    /// From a follower whose button was pressed.
+    Next,
+}
+
+fn send(mut stream: &TcpStream, message: &Message) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())
+}
+
+fn receive<F: FnMut(Message)>(stream: TcpStream, mut handle: F) {
+    for line in BufReader::new(stream).lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        match serde_json::from_str(&line) {
+            Ok(message) => handle(message),
+            Err(e) => warn!("unexpected sync message {:?}: {}", line, e),
+        }
+    }
+}
+
+/// The `[sync]` section of the config file: either the address to lead a
+/// session on or the address of the leader to follow.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(try_from = "RawSyncConfig")]
+pub enum SyncConfig {
+    Listen(String),
+    Leader(String),
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawSyncConfig {
+    listen: Option<String>,
+    leader: Option<String>,
+}
+
+impl TryFrom<RawSyncConfig> for SyncConfig {
+    type Error = String;
+
+    fn try_from(raw: RawSyncConfig) -> Result<Self, Self::Error> {
+        match (raw.listen, raw.leader) {
+            (Some(addr), None) => Ok(SyncConfig::Listen(addr)),
+            (None, Some(addr)) => Ok(SyncConfig::Leader(addr)),
+            _ => Err("set either listen, to lead a session, or leader, to follow one".to_string()),
+        }
+    }
+}
+
+pub enum Session {
+    Leader(Leader),
+    Follower(Follower),
+}
+
+impl Session {
+    /// Starts leading or following in the background, handing anything that
+    /// needs acting on to `inbox`.
+    pub fn start(config: &SyncConfig, inbox: Sender) -> io::Result<Self> {
+        match config {
+            SyncConfig::Listen(addr) => Leader::start(addr, inbox).map(Session::Leader),
+            SyncConfig::Leader(addr) => Ok(Session::Follower(Follower::start(addr, inbox))),
+        }
+    }
+}
+
+/// Keeps followers in step with this instance.
+pub struct Leader {
+    addr: SocketAddr,
+    followers: Arc<Mutex<Vec<mpsc::Sender<Update>>>>,
+    /// The last update and when it was sent, to catch up anyone joining later.
+    last: Arc<Mutex<Option<(Update, Instant)>>>,
+}
+
+impl Leader {
+    pub fn start(addr: &str, inbox: Sender) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let leader = Self {
+            addr: listener.local_addr()?,
+            followers: Arc::default(),
+            last: Arc::default(),
+        };
+        info!("Leading a shared session on {}", leader.addr());
+        let followers = leader.followers.clone();
+        let last = leader.last.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        warn!("failed to accept follower: {}", e);
+                        continue;
+                    }
+                };
+                if let Ok(peer) = stream.peer_addr() {
+                    info!("{} joined the session", peer);
+                }
+                if stream.set_write_timeout(Some(WRITE_TIMEOUT)).is_err() {
+                    continue;
+                }
+                let writer = match stream.try_clone() {
+                    Ok(clone) => forward(clone),
+                    Err(_) => continue,
+                };
+                // hold the followers while catching up so a publish can't
+                // slip in between
+                let mut followers = followers.lock().unwrap();
+                if let Some((update, sent)) = &*last.lock().unwrap() {
+                    let _ = writer.send(update.aged(sent.elapsed()));
+                }
+                followers.push(writer);
+                drop(followers);
+                let inbox = inbox.clone();
+                thread::spawn(move || {
+                    receive(stream, |message| {
+                        if message == Message::Next {
+                            inbox.send(Command::Press(ButtonPress::Primary));
+                        }
+                    })
+                });
+            }
+        });
+        Ok(leader)
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Sends where we're up to to every follower, dropping any that have gone.
+    pub fn publish(&self, update: Update) {
+        let mut followers = self.followers.lock().unwrap();
+        followers.retain(|follower| follower.send(update.clone()).is_ok());
+        *self.last.lock().unwrap() = Some((update, Instant::now()));
+    }
+}
+
+/// Writes updates to a follower from a thread of its own, so a slow one holds
+/// up neither the timer nor the others. Hangs up on the follower once a write
+/// fails or times out, which drops it at the next publish.
+fn forward(stream: TcpStream) -> mpsc::Sender<Update> {
+    let (sender, receiver) = mpsc::channel::<Update>();
+    thread::spawn(move || {
+        for update in receiver {
+            if send(&stream, &Message::Update(update)).is_err() {
+                break;
+            }
+        }
+        let _ = stream.shutdown(Shutdown::Both);
+    });
+    sender
+}
+
+/// Follows the phases of a leader, reconnecting whenever the connection drops.
+pub struct Follower {
+    leader: Arc<Mutex<Option<TcpStream>>>,
+}
+
+impl Follower {
+    pub fn start(addr: &str, inbox: Sender) -> Self {
+        let follower = Self {
+            leader: Arc::default(),
+        };
+        let leader = follower.leader.clone();
+        let addr = addr.to_string();
+        thread::spawn(move || loop {
+            match TcpStream::connect(&addr) {
+                Ok(stream) => {
+                    info!("Joined the session led by {}", addr);
+                    let _ = stream.set_write_timeout(Some(WRITE_TIMEOUT));
+                    *leader.lock().unwrap() = stream.try_clone().ok();
+                    receive(stream, |message| {
+                        if let Message::Update(update) = message {
+                            inbox.send(Command::Follow(update));
+                        }
+                    });
+                    *leader.lock().unwrap() = None;
+                    warn!("Lost the connection to {}", addr);
+                }
+                Err(e) => warn!("failed to join the session led by {}: {}", addr, e),
+            }
+            sleep(RECONNECT_INTERVAL);
+        });
+        follower
+    }
+
+    /// Asks the leader to move everyone on, returning whether it could be
+    /// reached.
+    pub fn request_next(&self) -> bool {
+        match &*self.leader.lock().unwrap() {
+            Some(stream) => send(stream, &Message::Next).is_ok(),
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::ButtonSignal;
+
+    fn update(state: State) -> Update {
+        Update {
+            state,
+            elapsed_ms: 0,
+            overdue: false,
+            intervals: 1,
+            paused: false,
+        }
+    }
+
+    #[test]
+    fn test_config_reads_either_role() {
+        let config: SyncConfig = toml::from_str("leader = \"10.0.0.2:4299\"").unwrap();
+        assert_eq!(config, SyncConfig::Leader("10.0.0.2:4299".to_string()));
+        let config: SyncConfig = toml::from_str("listen = \"0.0.0.0:4299\"").unwrap();
+        assert_eq!(config, SyncConfig::Listen("0.0.0.0:4299".to_string()));
+        assert!(toml::from_str::<SyncConfig>("").is_err());
+    }
+
+    #[test]
+    fn test_updates_reach_followers_and_presses_reach_the_leader() {
+        let mut leader_signal = ButtonSignal::in_process().unwrap();
+        let mut follower_signal = ButtonSignal::in_process().unwrap();
+        let leader = Leader::start("127.0.0.1:0", leader_signal.sender()).unwrap();
+        leader.publish(update(State::Working));
+        let follower = Follower::start(&leader.addr().to_string(), follower_signal.sender());
+        let timeout = Some(Duration::from_secs(5));
+        // joining catches up with the last update
+        match follower_signal.wait(timeout).unwrap() {
+            Some(Command::Follow(joined)) => assert_eq!(joined.state, State::Working),
+            other => panic!("expected an update, got {:?}", other),
+        }
+        leader.publish(update(State::ShortBreak));
+        assert_eq!(
+            follower_signal.wait(timeout).unwrap(),
+            Some(Command::Follow(update(State::ShortBreak)))
+        );
+        assert!(follower.request_next());
+        assert_eq!(
+            leader_signal.wait(timeout).unwrap(),
+            Some(Command::Press(ButtonPress::Primary))
+        );
+    }
+}
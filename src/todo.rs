@@ -0,0 +1,175 @@
+use crate::files;
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+const POMO_TAG: &str = "pomo:";
+
+/// A plain-text task list, either in todo.txt format or, for `.md` files, a
+/// Markdown checklist.
+pub struct TodoFile {
+    path: PathBuf,
+}
+
+impl TodoFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// Labels of the tasks not yet done, in file order.
+    pub fn open_tasks(&self) -> io::Result<Vec<String>> {
+        let contents = fs::read_to_string(&self.path)?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| self.open_task(line))
+            .map(label)
+            .collect())
+    }
+
+    /// Bumps the `pomo:` count on the first open task with the given label.
+    /// Returns whether a matching task was found.
+    pub fn add_pomodoro(&self, task_label: &str) -> io::Result<bool> {
+        let contents = fs::read_to_string(&self.path)?;
+        let mut found = false;
+        let mut updated = String::with_capacity(contents.len() + POMO_TAG.len() + 2);
+        // keep each line's own ending, be it CRLF, LF or none at the end
+        for chunk in contents.split_inclusive('\n') {
+            let line = chunk.strip_suffix('\n').unwrap_or(chunk);
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            match self.open_task(line) {
+                Some(task) if !found && label(task) == task_label => {
+                    found = true;
+                    updated.push_str(&increment_pomodoros(line));
+                }
+                _ => updated.push_str(line),
+            }
+            updated.push_str(&chunk[line.len()..]);
+        }
+        if found {
+            files::replace(&self.path, &updated)?;
+        }
+        Ok(found)
+    }
+
+    /// The text of the task on `line` if it is still open.
+    fn open_task<'a>(&self, line: &'a str) -> Option<&'a str> {
+        if self.is_markdown() {
+            let item = line.trim_start();
+            let item = item
+                .strip_prefix("- [ ]")
+                .or_else(|| item.strip_prefix("* [ ]"))?;
+            Some(item.trim()).filter(|task| !task.is_empty())
+        } else {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("x ") {
+                None
+            } else {
+                Some(line)
+            }
+        }
+    }
+
+    fn is_markdown(&self) -> bool {
+        self.path.extension().is_some_and(|ext| ext == "md")
+    }
+}
+
+fn label(task: &str) -> String {
+    task.split_whitespace()
+        .filter(|word| !word.starts_with(POMO_TAG))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn increment_pomodoros(line: &str) -> String {
+    let mut end = 0;
+    for word in line.split_whitespace() {
+        let start = end + line[end..].find(word).unwrap_or(0);
+        end = start + word.len();
+        let count = word
+            .strip_prefix(POMO_TAG)
+            .and_then(|count| count.parse::<u32>().ok());
+        if let Some(count) = count {
+            // replace just this word, which others may start with
+            return format!(
+                "{}{}{}{}",
+                &line[..start],
+                POMO_TAG,
+                count + 1,
+                &line[end..]
+            );
+        }
+    }
+    format!("{} {}1", line.trim_end(), POMO_TAG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn todo_file(name: &str, contents: &str) -> TodoFile {
+        let path = env::temp_dir().join(format!("tomatina-{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        TodoFile::new(path)
+    }
+
+    #[test]
+    fn test_open_tasks_skips_completed_todo_txt_lines() {
+        let todo = todo_file(
+            "todo.txt",
+            "(A) write report +work pomo:2\nx 2020-01-01 file taxes\n\ncall mum\n",
+        );
+        assert_eq!(
+            todo.open_tasks().unwrap(),
+            vec!["(A) write report +work", "call mum"]
+        );
+        fs::remove_file(todo.path).unwrap();
+    }
+
+    #[test]
+    fn test_open_tasks_reads_markdown_checklists() {
+        let todo = todo_file(
+            "todo.md",
+            "# Today\n\n- [ ] write report\n- [x] file taxes\n  * [ ] call mum\nnotes\n",
+        );
+        assert_eq!(todo.open_tasks().unwrap(), vec!["write report", "call mum"]);
+        fs::remove_file(todo.path).unwrap();
+    }
+
+    #[test]
+    fn test_add_pomodoro_appends_and_increments_the_count() {
+        let todo = todo_file("count.txt", "write report\ncall mum pomo:2 +home\n");
+        assert!(todo.add_pomodoro("write report").unwrap());
+        assert!(todo.add_pomodoro("call mum +home").unwrap());
+        assert!(!todo.add_pomodoro("file taxes").unwrap());
+        assert_eq!(
+            fs::read_to_string(&todo.path).unwrap(),
+            "write report pomo:1\ncall mum pomo:3 +home\n"
+        );
+        fs::remove_file(todo.path).unwrap();
+    }
+
+    #[test]
+    fn test_add_pomodoro_changes_only_the_counted_word() {
+        let todo = todo_file("word.txt", "ask about pomo:2x	pomo:2 \r\nx done\r\n");
+        assert!(todo.add_pomodoro("ask about").unwrap());
+        assert_eq!(
+            fs::read_to_string(&todo.path).unwrap(),
+            "ask about pomo:2x\tpomo:3 \r\nx done\r\n"
+        );
+        fs::remove_file(todo.path).unwrap();
+    }
+
+    #[test]
+    fn test_add_pomodoro_updates_markdown_items() {
+        let todo = todo_file("count.md", "- [x] write report\n- [ ] write report");
+        assert!(todo.add_pomodoro("write report").unwrap());
+        assert_eq!(
+            fs::read_to_string(&todo.path).unwrap(),
+            "- [x] write report\n- [ ] write report pomo:1"
+        );
+        fs::remove_file(todo.path).unwrap();
+    }
+}
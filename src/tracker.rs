@@ -1,9 +1,18 @@
-use std::time::{Duration, Instant};
+use crate::clock::Clock;
+use crate::history::{unix_secs, Outcome, Record};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime};
+
+/// A wall clock running this far ahead of the monotonic clock means the machine
+/// was asleep.
+const SUSPEND_THRESHOLD: Duration = Duration::from_secs(30);
 
 pub struct TrackerConfig {
     pub work_duration: Duration,
     pub short_break_duration: Duration,
     pub long_break_duration: Duration,
+    pub suspend_policy: SuspendPolicy,
 }
 
 impl Default for TrackerConfig {
@@ -12,11 +21,33 @@ impl Default for TrackerConfig {
             work_duration: Duration::from_secs(20 * 60),
             short_break_duration: Duration::from_secs(5 * 60),
             long_break_duration: Duration::from_secs(15 * 60),
+            suspend_policy: SuspendPolicy::Complete,
+        }
+    }
+}
+
+/// What to do with a running phase when the machine is suspended part way through.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SuspendPolicy {
+    /// Count the time asleep towards the phase, completing it if it has run out.
+    Complete,
+    /// Abandon the phase and wait for work to be started again.
+    Interrupt,
+}
+
+impl FromStr for SuspendPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "complete" => Ok(SuspendPolicy::Complete),
+            "interrupt" => Ok(SuspendPolicy::Interrupt),
+            _ => Err(format!("unknown suspend policy: {}", s)),
         }
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 pub enum State {
     PendingWork,
     Working,
@@ -26,31 +57,51 @@ pub enum State {
     LongBreak,
 }
 
-pub struct Tracker {
+pub struct Tracker<C: Clock> {
     pub state: State,
     entered_state: Instant,
+    entered_wall: SystemTime,
+    /// Whether the current pending state was reached by running out of time,
+    /// rather than at startup or after an interruption.
+    overdue: bool,
+    /// When the current phase was paused, if it is.
+    paused_at: Option<Instant>,
+    /// Time the current phase spent paused before `paused_at`.
+    paused: Duration,
     intervals: u64,
+    task: Option<String>,
+    records: Vec<Record>,
     config: TrackerConfig,
+    clock: C,
 }
 
-impl Tracker {
-    pub fn new(config: TrackerConfig) -> Self {
+impl<C: Clock> Tracker<C> {
+    pub fn new(config: TrackerConfig, clock: C) -> Self {
         Self {
             state: State::PendingWork,
-            entered_state: Instant::now(),
+            entered_state: clock.now(),
+            entered_wall: clock.system_time(),
+            overdue: false,
+            paused_at: None,
+            paused: Duration::from_secs(0),
             intervals: 0,
+            task: None,
+            records: Vec::new(),
             config,
+            clock,
         }
     }
 
     pub fn next(&mut self) {
+        self.resume();
+        self.record(self.clock.system_time(), Outcome::Completed);
         match self.state {
             State::PendingWork => {
                 self.enter_state(State::Working);
             }
             State::Working => {
                 self.intervals += 1;
-                if self.intervals % 4 == 0 {
+                if self.intervals.is_multiple_of(4) {
                     self.enter_state(State::LongBreak);
                 } else {
                     self.enter_state(State::ShortBreak);
@@ -68,45 +119,224 @@ impl Tracker {
         }
     }
 
-    pub fn tick(&mut self, now: Instant) {
-        match self.state {
-            State::PendingWork | State::PendingShortBreak | State::PendingLongBreak => {}
+    pub fn tick(&mut self) {
+        let duration = match self.phase_duration() {
+            Some(duration) => duration,
+            None => return,
+        };
+        if self.suspended() && self.config.suspend_policy == SuspendPolicy::Interrupt {
+            // The monotonic clock stops while suspended so this is roughly when
+            // the machine went to sleep.
+            let ended = self.entered_wall + (self.clock.now() - self.entered_state);
+            self.record(ended, Outcome::Interrupted);
+            self.enter_state(State::PendingWork);
+            return;
+        }
+        let elapsed = self.elapsed();
+        if elapsed < duration {
+            return;
+        }
+        self.record(
+            self.entered_wall + duration + self.paused_for(),
+            Outcome::Completed,
+        );
+        let next_state = match self.state {
             State::Working => {
-                if now.duration_since(self.entered_state) >= self.config.work_duration {
-                    self.intervals += 1;
-                    if self.intervals % 4 == 0 {
-                        self.enter_state(State::PendingLongBreak);
-                    } else {
-                        self.enter_state(State::PendingShortBreak);
-                    }
-                }
-            }
-            State::ShortBreak => {
-                if now.duration_since(self.entered_state) >= self.config.short_break_duration {
-                    self.enter_state(State::PendingWork);
-                }
-            }
-            State::LongBreak => {
-                if now.duration_since(self.entered_state) >= self.config.long_break_duration {
-                    self.enter_state(State::PendingWork);
+                self.intervals += 1;
+                if self.intervals.is_multiple_of(4) {
+                    State::PendingLongBreak
+                } else {
+                    State::PendingShortBreak
                 }
             }
+            _ => State::PendingWork,
+        };
+        // backdate to when the phase actually ran out so overtime is measured from then
+        self.enter_state_since(next_state, elapsed - duration);
+        self.overdue = true;
+    }
+
+    /// Stops the clock on the running phase, backdated by `ago`.
+    pub fn pause(&mut self, ago: Duration) {
+        if self.phase_duration().is_none() || self.paused_at.is_some() {
+            return;
         }
+        let now = self.clock.now();
+        let ago = ago.min(self.elapsed());
+        self.paused_at = Some(now.checked_sub(ago).unwrap_or(now));
     }
 
-    pub fn time_remaining(&self, now: Instant) -> Option<Duration> {
-        let time_since_entered_state = now - self.entered_state;
-        match self.state {
+    pub fn resume(&mut self) {
+        if let Some(paused_at) = self.paused_at.take() {
+            self.paused += self.clock.now() - paused_at;
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_at.is_some()
+    }
+
+    /// Abandons whatever is going on and waits for work to be started again.
+    pub fn stop(&mut self) {
+        let outcome = if self.phase_duration().is_some() {
+            Outcome::Interrupted
+        } else {
+            Outcome::Completed
+        };
+        self.record(self.clock.system_time(), outcome);
+        self.enter_state(State::PendingWork);
+    }
+
+    /// Moves to the state another instance is in, `elapsed` into it and paused
+    /// if it is, recording the current phase as if it had been moved on by hand.
+    pub fn follow(
+        &mut self,
+        state: State,
+        elapsed: Duration,
+        overdue: bool,
+        intervals: u64,
+        paused: bool,
+    ) {
+        if state != self.state {
+            self.resume();
+            self.record(self.clock.system_time(), Outcome::Completed);
+        }
+        // the other instance can't be further into a phase than it lasts
+        let elapsed = self
+            .duration_of(state)
+            .map_or(elapsed, |duration| elapsed.min(duration));
+        self.enter_state_since(state, elapsed);
+        self.overdue = overdue;
+        self.intervals = intervals;
+        if paused {
+            self.pause(Duration::from_secs(0));
+        }
+    }
+
+    /// Starts counting towards the next long break afresh.
+    pub fn reset_intervals(&mut self) {
+        self.intervals = 0;
+    }
+
+    /// Work intervals completed towards the next long break.
+    pub fn intervals(&self) -> u64 {
+        self.intervals
+    }
+
+    pub fn time_remaining(&self) -> Option<Duration> {
+        self.phase_duration()
+            .map(|duration| duration.saturating_sub(self.elapsed()))
+    }
+
+    pub fn clock(&self) -> &C {
+        &self.clock
+    }
+
+    pub fn task(&self) -> Option<&str> {
+        self.task.as_deref()
+    }
+
+    /// Labels the current and following work intervals.
+    pub fn set_task(&mut self, task: Option<String>) {
+        self.task = task;
+    }
+
+    /// How long the current pending state has been waiting since the previous
+    /// phase ran out.
+    pub fn overtime(&self) -> Option<Duration> {
+        if self.overdue {
+            Some(self.elapsed())
+        } else {
+            None
+        }
+    }
+
+    /// Hands over the phases that have ended since the last call.
+    pub fn take_records(&mut self) -> Vec<Record> {
+        std::mem::take(&mut self.records)
+    }
+
+    /// How long the current phase runs for, if it's timed.
+    pub fn phase_duration(&self) -> Option<Duration> {
+        self.duration_of(self.state)
+    }
+
+    fn duration_of(&self, state: State) -> Option<Duration> {
+        match state {
             State::PendingWork | State::PendingShortBreak | State::PendingLongBreak => None,
-            State::Working => Some(self.config.work_duration - time_since_entered_state),
-            State::ShortBreak => Some(self.config.short_break_duration - time_since_entered_state),
-            State::LongBreak => Some(self.config.long_break_duration - time_since_entered_state),
+            State::Working => Some(self.config.work_duration),
+            State::ShortBreak => Some(self.config.short_break_duration),
+            State::LongBreak => Some(self.config.long_break_duration),
+        }
+    }
+
+    /// Time spent in the current state, including any time spent suspended but
+    /// not time spent paused.
+    pub fn elapsed(&self) -> Duration {
+        self.monotonic_elapsed()
+            .max(self.wall_elapsed())
+            .saturating_sub(self.paused_for())
+    }
+
+    fn paused_for(&self) -> Duration {
+        match self.paused_at {
+            Some(paused_at) => self.paused + (self.clock.now() - paused_at),
+            None => self.paused,
+        }
+    }
+
+    fn suspended(&self) -> bool {
+        self.wall_elapsed() > self.monotonic_elapsed() + SUSPEND_THRESHOLD
+    }
+
+    fn monotonic_elapsed(&self) -> Duration {
+        self.clock.now() - self.entered_state
+    }
+
+    fn wall_elapsed(&self) -> Duration {
+        self.clock
+            .system_time()
+            .duration_since(self.entered_wall)
+            .unwrap_or_default()
+    }
+
+    /// Pending states are only recorded when they follow a phase that ran out,
+    /// in which case the record captures the overtime.
+    fn record(&mut self, ended: SystemTime, outcome: Outcome) {
+        if self.phase_duration().is_none() && !self.overdue {
+            return;
         }
+        self.records.push(Record {
+            state: self.state,
+            started: unix_secs(self.entered_wall),
+            ended: unix_secs(ended),
+            outcome,
+            task: match self.state {
+                State::Working => self.task.clone(),
+                _ => None,
+            },
+            paused: self.paused_for().as_secs(),
+        });
     }
 
     fn enter_state(&mut self, state: State) {
+        self.enter_state_since(state, Duration::from_secs(0));
+    }
+
+    fn enter_state_since(&mut self, state: State, ago: Duration) {
+        let now = self.clock.now();
+        let wall = self.clock.system_time();
         self.state = state;
-        self.entered_state = Instant::now();
+        // further back than either clock goes, so start from now
+        let (entered_state, entered_wall) = match (now.checked_sub(ago), wall.checked_sub(ago)) {
+            (Some(entered_state), Some(entered_wall)) => (entered_state, entered_wall),
+            _ => (now, wall),
+        };
+        self.entered_state = entered_state;
+        self.entered_wall = entered_wall;
+        self.overdue = false;
+        self.paused_at = None;
+        self.paused = Duration::from_secs(0);
     }
 }
 
@@ -114,24 +344,30 @@ impl Tracker {
 #[allow(non_snake_case)]
 mod tests {
     use super::*;
+    use crate::clock::MockClock;
 
     const WORK_DURATION: Duration = Duration::from_secs(20 * 60);
     const SHORT_BREAK_DURATION: Duration = Duration::from_secs(5 * 60);
     const LONG_BREAK_DURATION: Duration = Duration::from_secs(15 * 60);
 
-    fn create_tracker() -> Tracker {
-        Tracker::new(Default::default())
+    fn create_tracker() -> Tracker<MockClock> {
+        Tracker::new(Default::default(), MockClock::new())
+    }
+
+    fn advance(tracker: &mut Tracker<MockClock>, duration: Duration) {
+        tracker.clock.advance(duration);
+        tracker.tick();
     }
 
-    fn tracker_at_short_break() -> Tracker {
+    fn tracker_at_short_break() -> Tracker<MockClock> {
         let mut tracker = create_tracker();
         tracker.next(); // -> working
-        tracker.tick(Instant::now() + WORK_DURATION); // -> pending short break
+        advance(&mut tracker, WORK_DURATION); // -> pending short break
         tracker.next(); // -> short break
         tracker
     }
 
-    fn tracker_at_pending_long_break() -> Tracker {
+    fn tracker_at_pending_long_break() -> Tracker<MockClock> {
         let mut tracker = create_tracker();
         work(&mut tracker);
         short_break(&mut tracker);
@@ -143,26 +379,16 @@ mod tests {
         tracker
     }
 
-    fn work(tracker: &mut Tracker) {
+    fn work(tracker: &mut Tracker<MockClock>) {
         // tracker must start at PendingWork
         tracker.next(); // -> working
-        tracker.tick(Instant::now() + WORK_DURATION); // -> pending short/long break
+        advance(tracker, WORK_DURATION); // -> pending short/long break
     }
 
-    fn short_break(tracker: &mut Tracker) {
+    fn short_break(tracker: &mut Tracker<MockClock>) {
         // tracker must start at PendingShortBreak
         tracker.next(); // -> short break
-        tracker.tick(Instant::now() + SHORT_BREAK_DURATION); // -> pending work
-    }
-
-    macro_rules! assert_approx_eq {
-        ($value:expr, $target:expr) => {{
-            assert!(
-                $value < $target + Duration::from_secs(1)
-                    && $value > $target - Duration::from_secs(1),
-                format!("expected {:?} to be approximately {:?}", $value, $target,)
-            );
-        }};
+        advance(tracker, SHORT_BREAK_DURATION); // -> pending work
     }
 
     #[test]
@@ -190,7 +416,7 @@ mod tests {
     fn calling_tick_within_20_mins_of_working_doesnt_transition() {
         let mut tracker = create_tracker();
         tracker.next();
-        tracker.tick(Instant::now() + Duration::from_secs(19 * 60));
+        advance(&mut tracker, Duration::from_secs(19 * 60));
         assert_eq!(tracker.state, State::Working);
     }
 
@@ -198,7 +424,7 @@ mod tests {
     fn calling_tick_after_20_mins_of_working_transitions_to_PendingShortBreak() {
         let mut tracker = create_tracker();
         tracker.next();
-        tracker.tick(Instant::now() + WORK_DURATION);
+        advance(&mut tracker, WORK_DURATION);
         assert_eq!(tracker.state, State::PendingShortBreak);
     }
 
@@ -206,7 +432,7 @@ mod tests {
     fn calling_tick_while_in_PendingWork_does_nothing() {
         let mut tracker = create_tracker();
         assert_eq!(tracker.state, State::PendingWork);
-        tracker.tick(Instant::now() + WORK_DURATION);
+        advance(&mut tracker, WORK_DURATION);
         assert_eq!(tracker.state, State::PendingWork);
     }
 
@@ -214,7 +440,7 @@ mod tests {
     fn calling_next_from_PendingShortBreak_transitions_to_ShortBreak() {
         let mut tracker = create_tracker();
         tracker.next();
-        tracker.tick(Instant::now() + WORK_DURATION);
+        advance(&mut tracker, WORK_DURATION);
         assert_eq!(tracker.state, State::PendingShortBreak);
         tracker.next();
         assert_eq!(tracker.state, State::ShortBreak);
@@ -230,14 +456,14 @@ mod tests {
     #[test]
     fn calling_tick_within_5_mins_of_short_break_doesnt_transition() {
         let mut tracker = tracker_at_short_break();
-        tracker.tick(Instant::now() + Duration::from_secs(4 * 60));
+        advance(&mut tracker, Duration::from_secs(4 * 60));
         assert_eq!(tracker.state, State::ShortBreak);
     }
 
     #[test]
     fn calling_tick_after_5_mins_of_short_break_transitions_to_PendingWorking() {
         let mut tracker = tracker_at_short_break();
-        tracker.tick(Instant::now() + SHORT_BREAK_DURATION);
+        advance(&mut tracker, SHORT_BREAK_DURATION);
         assert_eq!(tracker.state, State::PendingWork);
     }
 
@@ -280,7 +506,7 @@ mod tests {
     fn calling_tick_within_15_minutes_of_a_long_break_does_nothing() {
         let mut tracker = tracker_at_pending_long_break();
         tracker.next();
-        tracker.tick(Instant::now() + Duration::from_secs(14 * 60));
+        advance(&mut tracker, Duration::from_secs(14 * 60));
         assert_eq!(tracker.state, State::LongBreak);
     }
 
@@ -288,7 +514,7 @@ mod tests {
     fn calling_tick_after_15_minutes_of_a_long_break_transitions_to_PendingWork() {
         let mut tracker = tracker_at_pending_long_break();
         tracker.next();
-        tracker.tick(Instant::now() + LONG_BREAK_DURATION);
+        advance(&mut tracker, LONG_BREAK_DURATION);
         assert_eq!(tracker.state, State::PendingWork);
     }
 
@@ -311,7 +537,7 @@ mod tests {
         work(&mut tracker);
         assert_eq!(tracker.state, State::PendingLongBreak);
         tracker.next();
-        tracker.tick(Instant::now() + LONG_BREAK_DURATION);
+        advance(&mut tracker, LONG_BREAK_DURATION);
         // 5
         work(&mut tracker);
         assert_eq!(tracker.state, State::PendingShortBreak);
@@ -335,40 +561,40 @@ mod tests {
         let mut tracker = create_tracker();
 
         // PendingWork
-        assert_eq!(tracker.time_remaining(Instant::now()), None);
+        assert_eq!(tracker.time_remaining(), None);
 
         // Working
         tracker.next();
-        assert_approx_eq!(
-            tracker.time_remaining(Instant::now() + five_secs).unwrap(),
-            WORK_DURATION - five_secs
-        );
+        tracker.clock.advance(five_secs);
+        assert_eq!(tracker.time_remaining(), Some(WORK_DURATION - five_secs));
 
         // PendingShortBreak
-        tracker.tick(Instant::now() + WORK_DURATION);
-        assert_eq!(tracker.time_remaining(Instant::now()), None);
+        advance(&mut tracker, WORK_DURATION);
+        assert_eq!(tracker.time_remaining(), None);
 
         // ShortBreak
         tracker.next();
-        assert_approx_eq!(
-            tracker.time_remaining(Instant::now() + five_secs).unwrap(),
-            SHORT_BREAK_DURATION - five_secs
+        tracker.clock.advance(five_secs);
+        assert_eq!(
+            tracker.time_remaining(),
+            Some(SHORT_BREAK_DURATION - five_secs)
         );
 
         // PendingLongBreak
-        tracker.tick(Instant::now() + SHORT_BREAK_DURATION);
+        advance(&mut tracker, SHORT_BREAK_DURATION);
         work(&mut tracker);
         short_break(&mut tracker);
         work(&mut tracker);
         short_break(&mut tracker);
         work(&mut tracker);
-        assert_eq!(tracker.time_remaining(Instant::now()), None);
+        assert_eq!(tracker.time_remaining(), None);
 
         // LongBreak
         tracker.next();
-        assert_approx_eq!(
-            tracker.time_remaining(Instant::now() + five_secs).unwrap(),
-            LONG_BREAK_DURATION - five_secs
+        tracker.clock.advance(five_secs);
+        assert_eq!(
+            tracker.time_remaining(),
+            Some(LONG_BREAK_DURATION - five_secs)
         );
     }
 
@@ -396,4 +622,303 @@ mod tests {
         tracker.next();
         assert_eq!(tracker.state, State::LongBreak);
     }
+
+    #[test]
+    fn time_remaining_does_not_underflow_when_overdue() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        tracker.clock.advance(WORK_DURATION * 2);
+        assert_eq!(tracker.time_remaining(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn time_suspended_counts_towards_working() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        tracker.clock.advance(Duration::from_secs(60));
+        tracker.clock.suspend(Duration::from_secs(5 * 60));
+        tracker.tick();
+        assert_eq!(tracker.state, State::Working);
+        assert_eq!(
+            tracker.time_remaining(),
+            Some(WORK_DURATION - Duration::from_secs(6 * 60))
+        );
+    }
+
+    #[test]
+    fn suspending_past_the_end_of_working_completes_the_interval() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        let started = unix_secs(tracker.clock.system_time());
+        tracker.clock.suspend(Duration::from_secs(8 * 60 * 60));
+        tracker.tick();
+        assert_eq!(tracker.state, State::PendingShortBreak);
+        assert_eq!(
+            tracker.take_records(),
+            vec![Record {
+                state: State::Working,
+                started,
+                ended: started + WORK_DURATION.as_secs(),
+                outcome: Outcome::Completed,
+                task: None,
+                paused: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn suspending_with_interrupt_policy_abandons_working() {
+        let config = TrackerConfig {
+            suspend_policy: SuspendPolicy::Interrupt,
+            ..Default::default()
+        };
+        let mut tracker = Tracker::new(config, MockClock::new());
+        tracker.next();
+        let started = unix_secs(tracker.clock.system_time());
+        tracker.clock.advance(Duration::from_secs(60));
+        tracker.clock.suspend(Duration::from_secs(5 * 60));
+        tracker.tick();
+        assert_eq!(tracker.state, State::PendingWork);
+        assert_eq!(
+            tracker.take_records(),
+            vec![Record {
+                state: State::Working,
+                started,
+                ended: started + 60,
+                outcome: Outcome::Interrupted,
+                task: None,
+                paused: 0,
+            }]
+        );
+        // the abandoned interval doesn't count towards a long break
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        assert_eq!(tracker.state, State::PendingLongBreak);
+    }
+
+    #[test]
+    fn ended_phases_are_recorded_once() {
+        let mut tracker = create_tracker();
+        let started = unix_secs(tracker.clock.system_time());
+        work(&mut tracker);
+        tracker.next(); // -> short break
+        tracker.clock.advance(Duration::from_secs(60));
+        tracker.next(); // -> working
+        let work_ended = started + WORK_DURATION.as_secs();
+        assert_eq!(
+            tracker.take_records(),
+            vec![
+                Record {
+                    state: State::Working,
+                    started,
+                    ended: work_ended,
+                    outcome: Outcome::Completed,
+                    task: None,
+                    paused: 0,
+                },
+                Record {
+                    state: State::PendingShortBreak,
+                    started: work_ended,
+                    ended: work_ended,
+                    outcome: Outcome::Completed,
+                    task: None,
+                    paused: 0,
+                },
+                Record {
+                    state: State::ShortBreak,
+                    started: work_ended,
+                    ended: work_ended + 60,
+                    outcome: Outcome::Completed,
+                    task: None,
+                    paused: 0,
+                },
+            ]
+        );
+        assert_eq!(tracker.take_records(), vec![]);
+    }
+
+    #[test]
+    fn working_records_carry_the_task() {
+        let mut tracker = create_tracker();
+        tracker.set_task(Some("write report".to_string()));
+        work(&mut tracker);
+        short_break(&mut tracker);
+        let records = tracker.take_records();
+        assert_eq!(records[0].state, State::Working);
+        assert_eq!(records[0].task, Some("write report".to_string()));
+        assert!(records[1..].iter().all(|record| record.task.is_none()));
+    }
+
+    #[test]
+    fn stop_interrupts_a_running_phase() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        let started = unix_secs(tracker.clock.system_time());
+        tracker.clock.advance(Duration::from_secs(60));
+        tracker.stop();
+        assert_eq!(tracker.state, State::PendingWork);
+        assert_eq!(
+            tracker.take_records(),
+            vec![Record {
+                state: State::Working,
+                started,
+                ended: started + 60,
+                outcome: Outcome::Interrupted,
+                task: None,
+                paused: 0,
+            }]
+        );
+    }
+
+    #[test]
+    fn pausing_stops_the_clock_on_working() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        let started = unix_secs(tracker.clock.system_time());
+        tracker.clock.advance(Duration::from_secs(10 * 60));
+        tracker.pause(Duration::from_secs(5 * 60));
+        assert!(tracker.is_paused());
+        let remaining = WORK_DURATION - Duration::from_secs(5 * 60);
+        assert_eq!(tracker.time_remaining(), Some(remaining));
+        advance(&mut tracker, WORK_DURATION);
+        assert_eq!(tracker.state, State::Working);
+        assert_eq!(tracker.time_remaining(), Some(remaining));
+        tracker.resume();
+        assert!(!tracker.is_paused());
+        advance(&mut tracker, remaining);
+        assert_eq!(tracker.state, State::PendingShortBreak);
+        let paused = WORK_DURATION + Duration::from_secs(5 * 60);
+        assert_eq!(
+            tracker.take_records(),
+            vec![Record {
+                state: State::Working,
+                started,
+                ended: started + (WORK_DURATION + paused).as_secs(),
+                outcome: Outcome::Completed,
+                task: None,
+                paused: paused.as_secs(),
+            }]
+        );
+    }
+
+    #[test]
+    fn pausing_does_nothing_while_pending() {
+        let mut tracker = create_tracker();
+        tracker.pause(Duration::from_secs(0));
+        assert!(!tracker.is_paused());
+    }
+
+    #[test]
+    fn following_another_instance_lines_up_the_phase() {
+        let mut tracker = create_tracker();
+        tracker.follow(State::Working, Duration::from_secs(5 * 60), false, 2, false);
+        assert_eq!(tracker.state, State::Working);
+        assert_eq!(
+            tracker.time_remaining(),
+            Some(WORK_DURATION - Duration::from_secs(5 * 60))
+        );
+        tracker.follow(
+            State::PendingShortBreak,
+            Duration::from_secs(60),
+            true,
+            3,
+            false,
+        );
+        assert_eq!(tracker.overtime(), Some(Duration::from_secs(60)));
+        assert_eq!(tracker.intervals(), 3);
+        let records = tracker.take_records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].state, State::Working);
+        assert_eq!(records[0].outcome, Outcome::Completed);
+    }
+
+    #[test]
+    fn following_a_paused_phase_stops_the_clock() {
+        let mut tracker = create_tracker();
+        tracker.follow(State::Working, Duration::from_secs(5 * 60), false, 0, true);
+        assert!(tracker.is_paused());
+        advance(&mut tracker, Duration::from_secs(60));
+        assert_eq!(tracker.elapsed(), Duration::from_secs(5 * 60));
+        tracker.follow(State::Working, Duration::from_secs(5 * 60), false, 0, false);
+        assert!(!tracker.is_paused());
+        assert!(tracker.take_records().is_empty());
+    }
+
+    #[test]
+    fn following_a_phase_from_out_of_range_is_bounded() {
+        let mut tracker = create_tracker();
+        tracker.follow(State::Working, Duration::MAX, false, 0, false);
+        assert_eq!(tracker.time_remaining(), Some(Duration::from_secs(0)));
+        tracker.follow(State::PendingWork, Duration::MAX, true, 0, false);
+        assert_eq!(tracker.overtime(), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn reset_intervals_restarts_the_count_to_a_long_break() {
+        let mut tracker = create_tracker();
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        short_break(&mut tracker);
+        tracker.reset_intervals();
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        assert_eq!(tracker.state, State::PendingShortBreak);
+        short_break(&mut tracker);
+        work(&mut tracker);
+        assert_eq!(tracker.state, State::PendingLongBreak);
+    }
+
+    #[test]
+    fn overtime_is_only_tracked_after_a_phase_runs_out() {
+        let mut tracker = create_tracker();
+        tracker.clock.advance(Duration::from_secs(60));
+        assert_eq!(tracker.overtime(), None);
+        tracker.next();
+        tracker.clock.advance(Duration::from_secs(60));
+        assert_eq!(tracker.overtime(), None);
+    }
+
+    #[test]
+    fn overtime_is_measured_from_when_the_phase_ran_out() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        advance(&mut tracker, WORK_DURATION + Duration::from_secs(30));
+        assert_eq!(tracker.state, State::PendingShortBreak);
+        assert_eq!(tracker.overtime(), Some(Duration::from_secs(30)));
+        tracker.clock.advance(Duration::from_secs(90));
+        assert_eq!(tracker.overtime(), Some(Duration::from_secs(120)));
+        tracker.next();
+        assert_eq!(tracker.overtime(), None);
+    }
+
+    #[test]
+    fn overtime_is_recorded_in_history() {
+        let mut tracker = create_tracker();
+        tracker.next();
+        let started = unix_secs(tracker.clock.system_time());
+        advance(&mut tracker, WORK_DURATION);
+        tracker.clock.advance(Duration::from_secs(3 * 60));
+        tracker.next(); // -> short break
+        let work_ended = started + WORK_DURATION.as_secs();
+        assert_eq!(
+            tracker.take_records()[1],
+            Record {
+                state: State::PendingShortBreak,
+                started: work_ended,
+                ended: work_ended + 3 * 60,
+                outcome: Outcome::Completed,
+                task: None,
+                paused: 0,
+            }
+        );
+    }
 }
@@ -0,0 +1,246 @@
+use crate::tracker::State;
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc;
+use std::thread::{self, sleep};
+use std::time::Duration;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait before the first retry, doubling after each failure.
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+
+/// What a webhook is sent when the state changes.
+#[derive(Debug, PartialEq, Clone, Serialize)]
+pub struct Transition {
+    pub state: State,
+    pub previous_state: State,
+    /// Work intervals completed towards the next long break.
+    pub intervals: u64,
+    /// How long the new phase runs for, if it's timed.
+    pub duration_secs: Option<u64>,
+    /// How long was spent in the previous state, not counting pauses.
+    pub previous_duration_secs: u64,
+    pub task: Option<String>,
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Template {
+    /// The transition as it is.
+    #[default]
+    Json,
+    /// A `text` message, as Slack and Mattermost incoming webhooks expect.
+    Slack,
+}
+
+impl Template {
+    fn render(self, transition: &Transition) -> String {
+        match self {
+            Template::Json => serde_json::to_string(transition).unwrap(),
+            Template::Slack => serde_json::json!({ "text": message(transition) }).to_string(),
+        }
+    }
+}
+
+fn message(transition: &Transition) -> String {
+    let mins = transition.duration_secs.unwrap_or(0) / 60;
+    match (transition.state, &transition.task) {
+        (State::Working, Some(task)) => format!(":tomato: Focusing on {} for {} mins", task, mins),
+        (State::Working, None) => format!(":tomato: Focusing for {} mins", mins),
+        (State::ShortBreak, _) => format!(":coffee: On a short break for {} mins", mins),
+        (State::LongBreak, _) => format!(":coffee: On a long break for {} mins", mins),
+        (State::PendingWork, _) => ":hourglass: Break over, back to work shortly".to_string(),
+        (State::PendingShortBreak, _) | (State::PendingLongBreak, _) => {
+            ":white_check_mark: Finished a pomodoro".to_string()
+        }
+    }
+}
+
+/// A `[[webhooks]]` entry in the config file.
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    #[serde(default)]
+    pub template: Template,
+    /// Further attempts to make before giving up on a transition.
+    #[serde(default = "default_retries")]
+    pub retries: u32,
+}
+
+fn default_retries() -> u32 {
+    3
+}
+
+/// Posts transitions to each webhook from a thread of its own, so a slow one
+/// holds up neither the timer nor the others, and each sees them in order.
+#[derive(Default)]
+pub struct Webhooks {
+    queues: Vec<mpsc::Sender<Transition>>,
+}
+
+impl Webhooks {
+    pub fn start(configs: &[WebhookConfig]) -> Self {
+        Self {
+            queues: configs
+                .iter()
+                .map(|config| deliver(config.clone(), FIRST_BACKOFF))
+                .collect(),
+        }
+    }
+
+    pub fn notify(&self, transition: &Transition) {
+        for queue in &self.queues {
+            let _ = queue.send(transition.clone());
+        }
+    }
+}
+
+fn deliver(config: WebhookConfig, backoff: Duration) -> mpsc::Sender<Transition> {
+    let (sender, receiver) = mpsc::channel::<Transition>();
+    thread::spawn(move || {
+        for transition in receiver {
+            let body = config.template.render(&transition);
+            if let Err(e) = post(&config.url, &body, config.retries, backoff) {
+                // the URL itself is often a secret, so only name the host
+                warn!("failed to send webhook to {}: {}", host(&config.url), e);
+            }
+        }
+    });
+    sender
+}
+
+/// Posts `body` to `url`, retrying server errors and failed connections with
+/// exponential backoff.
+fn post(url: &str, body: &str, retries: u32, mut backoff: Duration) -> Result<(), String> {
+    let mut attempt = 0;
+    loop {
+        let error = match ureq::post(url)
+            .timeout(REQUEST_TIMEOUT)
+            .set("Content-Type", "application/json")
+            .send_string(body)
+        {
+            Ok(_) => return Ok(()),
+            Err(ureq::Error::Status(status, _)) if status == 429 || status >= 500 => {
+                format!("server responded {}", status)
+            }
+            Err(ureq::Error::Status(status, _)) => {
+                return Err(format!("server responded {}", status));
+            }
+            Err(ureq::Error::Transport(transport)) => match transport.message() {
+                Some(message) => format!("{}: {}", transport.kind(), message),
+                None => transport.kind().to_string(),
+            },
+        };
+        if attempt == retries {
+            return Err(error);
+        }
+        sleep(backoff);
+        backoff *= 2;
+        attempt += 1;
+    }
+}
+
+fn host(url: &str) -> &str {
+    url.split('/').nth(2).unwrap_or(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread::JoinHandle;
+
+    fn transition() -> Transition {
+        Transition {
+            state: State::Working,
+            previous_state: State::PendingWork,
+            intervals: 2,
+            duration_secs: Some(1500),
+            previous_duration_secs: 30,
+            task: Some("reports".to_string()),
+            at: 1000,
+        }
+    }
+
+    /// Answers a request with each of `statuses` in turn, returning the bodies
+    /// it was sent.
+    fn stand_in(statuses: &'static [&'static str]) -> (String, JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_slack_template_describes_the_phase() {
+        let body: serde_json::Value =
+            serde_json::from_str(&Template::Slack.render(&transition())).unwrap();
+        assert_eq!(body["text"], ":tomato: Focusing on reports for 25 mins");
+    }
+
+    #[test]
+    fn test_webhook_receives_transitions() {
+        let (url, server) = stand_in(&["200 OK"]);
+        let webhooks = Webhooks::start(&[WebhookConfig {
+            url,
+            template: Template::Json,
+            retries: 0,
+        }]);
+        webhooks.notify(&transition());
+        let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()[0]).unwrap();
+        assert_eq!(body["state"], "Working");
+        assert_eq!(body["previous_state"], "PendingWork");
+        assert_eq!(body["intervals"], 2);
+        assert_eq!(body["duration_secs"], 1500);
+        assert_eq!(body["task"], "reports");
+    }
+
+    #[test]
+    fn test_post_retries_server_errors_but_not_client_errors() {
+        let backoff = Duration::from_millis(10);
+        let (url, server) = stand_in(&["503 Service Unavailable", "200 OK"]);
+        assert_eq!(post(&url, "{}", 3, backoff), Ok(()));
+        assert_eq!(server.join().unwrap().len(), 2);
+
+        let (url, server) = stand_in(&["404 Not Found"]);
+        assert_eq!(
+            post(&url, "{}", 3, backoff),
+            Err("server responded 404".to_string())
+        );
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+}